@@ -0,0 +1,149 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    ingredient::{IngredientIndex, Ingredients},
+    link::{Link, LinkFlow},
+    quantity::Quantity,
+    recipe::{RecipeIndex, Recipes},
+    transport::{Logistics, Supply},
+};
+
+#[derive(Debug)]
+pub struct BeltTier {
+    pub name: String,
+    /// Items per second a link with this belt can carry
+    pub capacity: f64,
+    /// Paid for every link upgraded to this tier
+    pub cost: Vec<(IngredientIndex, Quantity)>,
+}
+
+impl BeltTier {
+    pub fn can_afford(&self, ingredients: &Ingredients) -> bool {
+        self.cost
+            .iter()
+            .all(|&(ty, amount)| ingredients.get(ty).current >= amount.value())
+    }
+}
+
+/// The belts links can be built from, from slowest to fastest. Links start out with the first.
+#[derive(Resource, Default)]
+pub struct BeltTiers {
+    tiers: Vec<BeltTier>,
+}
+
+impl BeltTiers {
+    pub fn add_tier(&mut self, tier: BeltTier) {
+        self.tiers.push(tier);
+    }
+
+    pub fn get(&self, tier: usize) -> Option<&BeltTier> {
+        self.tiers.get(tier)
+    }
+
+    /// Without any tiers configured, links can carry any amount
+    pub fn capacity(&self, tier: usize) -> f64 {
+        self.get(tier).map_or(f64::INFINITY, |t| t.capacity)
+    }
+}
+
+/// The belt a link is built from, which decides how many items per second it carries
+#[derive(Component, Debug, Default)]
+pub struct Belt {
+    pub tier: usize,
+}
+
+impl Belt {
+    pub fn capacity(&self, tiers: &BeltTiers) -> f64 {
+        tiers.capacity(self.tier)
+    }
+}
+
+#[derive(Event, Debug)]
+pub enum BeltEvent {
+    /// Replaces the belt of the link with the next tier
+    Upgrade(Entity),
+}
+
+fn add_belts(mut commands: Commands, query: Query<Entity, Added<Link>>) {
+    for e in query.iter() {
+        commands.entity(e).insert(Belt::default());
+    }
+}
+
+/// Slows recipes down so none of their links has to carry more than its capacity. Only items
+/// travelling along the links in logistics mode are held up by their belts.
+fn throttle_recipes(
+    link_query: Query<(&LinkFlow, &Belt, Option<&Supply>)>,
+    tiers: Res<BeltTiers>,
+    mut recipes: ResMut<Recipes>,
+    logistics: Res<Logistics>,
+) {
+    let mut cycle_times: HashMap<RecipeIndex, f64> = HashMap::new();
+    for (flow, belt, supply) in link_query.iter() {
+        let cycle_time = cycle_times.entry(flow.recipe).or_insert(0.0);
+        if !logistics.enabled {
+            continue;
+        }
+
+        // Each link only carries its ingredient one way, even if the recipe both uses and
+        // makes it
+        let recipe = recipes.get_recipe(&flow.recipe);
+        let amount = match supply {
+            Some(_) => recipe.amount_consumed(flow.ingredient),
+            None => recipe.amount_produced(flow.ingredient),
+        };
+        *cycle_time = f64::max(*cycle_time, amount / belt.capacity(&tiers));
+    }
+
+    for (i, cycle_time) in cycle_times {
+        let holder = recipes.get_recipe_holder_mut(&i);
+        holder.link_cycle_time = cycle_time;
+    }
+}
+
+fn process_belt_events(
+    mut reader: EventReader<BeltEvent>,
+    mut belt_query: Query<&mut Belt>,
+    tiers: Res<BeltTiers>,
+    mut ingredients: ResMut<Ingredients>,
+) {
+    for event in reader.iter() {
+        match event {
+            BeltEvent::Upgrade(e) => {
+                let Ok(mut belt) = belt_query.get_mut(*e) else {
+                    warn!("Tried to upgrade the belt of something that isn't a link");
+                    continue;
+                };
+                let Some(next) = tiers.get(belt.tier + 1) else {
+                    warn!("Tried to upgrade a belt that's already at the last tier");
+                    continue;
+                };
+                if !next.can_afford(&ingredients) {
+                    warn!(
+                        "Tried to upgrade a belt to {} without meeting its cost",
+                        next.name
+                    );
+                    continue;
+                }
+
+                for (ty, amount) in &next.cost {
+                    ingredients.get_mut(*ty).spend_ingredient(amount.value());
+                }
+                belt.tier += 1;
+            }
+        }
+    }
+}
+
+pub struct BeltPlugin;
+
+impl Plugin for BeltPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BeltEvent>()
+            .init_resource::<BeltTiers>()
+            .add_systems(
+                Update,
+                (add_belts, process_belt_events, throttle_recipes).chain(),
+            );
+    }
+}
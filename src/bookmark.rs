@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{CameraFocus, CameraOrientation, CameraZoom, MainCamera, SetTarget},
+    ingredient::Ingredients,
+    node::{Node, NodeRegistry, NodeType},
+    recipe::Recipes,
+    save::SaveData,
+    ui::not_typing,
+};
+
+/// A stored camera position the player can jump back to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraBookmark {
+    pub target: [f32; 3],
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Id of the ingredient or recipe whose node was being followed, if any
+    pub node: Option<String>,
+}
+
+/// Bookmarks are numbered from 1 to 9, matching the keys used to store and recall them
+#[derive(Event, Debug)]
+pub enum BookmarkEvent {
+    Store(u8),
+    Jump(u8),
+    Clear(u8),
+}
+
+/// Ctrl+1..9 stores a bookmark and 1..9 jumps to it
+fn bookmark_keys(keys: Res<Input<KeyCode>>, mut writer: EventWriter<BookmarkEvent>) {
+    const DIGITS: [KeyCode; 9] = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    for (i, key) in DIGITS.iter().enumerate() {
+        if keys.just_pressed(*key) {
+            let slot = i as u8 + 1;
+            writer.send(match ctrl {
+                true => BookmarkEvent::Store(slot),
+                false => BookmarkEvent::Jump(slot),
+            });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn process_bookmark_events(
+    mut camera_query: Query<
+        (&mut CameraFocus, &mut CameraZoom, &mut CameraOrientation),
+        With<MainCamera>,
+    >,
+    node_query: Query<&Node>,
+    node_registry: Res<NodeRegistry>,
+    ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    mut save_data: ResMut<SaveData>,
+    mut reader: EventReader<BookmarkEvent>,
+    mut writer: EventWriter<SetTarget>,
+) {
+    let Ok((mut focus, mut zoom, mut orientation)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    for event in reader.iter() {
+        match *event {
+            BookmarkEvent::Store(slot) => {
+                let node = focus
+                    .entity()
+                    .and_then(|e| node_query.get(e).ok())
+                    .map(|node| node.ty.id(&ingredients, &recipes).to_string());
+
+                let bookmark = CameraBookmark {
+                    target: focus.target().to_array(),
+                    distance: zoom.distance(),
+                    yaw: orientation.yaw(),
+                    pitch: orientation.pitch(),
+                    node,
+                };
+                save_data.bookmarks.insert(slot, bookmark);
+            }
+            BookmarkEvent::Jump(slot) => {
+                let Some(bookmark) = save_data.bookmarks.get(&slot) else {
+                    continue;
+                };
+
+                // Follow the bookmarked node if it still exists, since it may have moved
+                let node_e = bookmark
+                    .node
+                    .as_deref()
+                    .and_then(|id| NodeType::from_id(id, &ingredients, &recipes))
+                    .and_then(|ty| node_registry.get(&ty));
+                match node_e {
+                    Some(e) => writer.send(SetTarget(*e)),
+                    None => focus.set_target(Vec3::from_array(bookmark.target)),
+                }
+                zoom.set_distance(bookmark.distance);
+                orientation.set(bookmark.yaw, bookmark.pitch);
+            }
+            BookmarkEvent::Clear(slot) => {
+                save_data.bookmarks.remove(&slot);
+            }
+        }
+    }
+}
+
+pub struct BookmarkPlugin;
+
+impl Plugin for BookmarkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BookmarkEvent>().add_systems(
+            Update,
+            (bookmark_keys.run_if(not_typing), process_bookmark_events).chain(),
+        );
+    }
+}
@@ -1,19 +1,45 @@
-use bevy::{input::mouse::MouseWheel, prelude::*};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_8};
+
+use bevy::{
+    input::mouse::{MouseMotion, MouseWheel},
+    prelude::*,
+    render::camera::ScalingMode,
+    window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+use bevy_mod_picking::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::{node::Node, ui::not_typing};
 
 #[derive(Component)]
 pub struct MainCamera;
 
 #[derive(Component)]
 pub struct CameraZoom {
+    /// The distance the camera is moving towards
     distance: f32,
+    /// The distance actually used this frame, smoothed towards `distance`
+    current_distance: f32,
     min_dist: f32,
     max_dist: f32,
 }
 
+impl CameraZoom {
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn set_distance(&mut self, distance: f32) {
+        self.distance = f32::clamp(distance, self.min_dist, self.max_dist);
+    }
+}
+
 impl Default for CameraZoom {
     fn default() -> Self {
         CameraZoom {
             distance: 15.0,
+            current_distance: 15.0,
             min_dist: 5.0,
             max_dist: 30.0,
         }
@@ -23,34 +49,187 @@ impl Default for CameraZoom {
 #[derive(Component, Default)]
 pub struct CameraFocus {
     target: Vec3,
+    /// The entity being followed, if the focus was set with `SetTarget`
+    entity: Option<Entity>,
+}
+
+impl CameraFocus {
+    pub fn target(&self) -> Vec3 {
+        self.target
+    }
+
+    pub fn entity(&self) -> Option<Entity> {
+        self.entity
+    }
+
+    /// Focuses on a fixed point, to follow an entity send `SetTarget` instead
+    pub fn set_target(&mut self, target: Vec3) {
+        self.target = target;
+        self.entity = None;
+    }
+}
+
+/// Which way the camera looks, in radians. Pitch is measured downwards from the horizon.
+#[derive(Component)]
+pub struct CameraOrientation {
+    yaw: f32,
+    pitch: f32,
+    min_pitch: f32,
+    max_pitch: f32,
+}
+
+impl CameraOrientation {
+    fn from_rotation(rotation: Quat) -> Self {
+        let (yaw, pitch, _) = rotation.to_euler(EulerRot::YXZ);
+        CameraOrientation {
+            yaw,
+            pitch: -pitch,
+            min_pitch: 0.2,
+            max_pitch: 1.45,
+        }
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn set(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = yaw;
+        self.pitch = f32::clamp(pitch, self.min_pitch, self.max_pitch);
+    }
+
+    fn add_pitch(&mut self, delta: f32) {
+        self.pitch = f32::clamp(self.pitch + delta, self.min_pitch, self.max_pitch);
+    }
+
+    fn rotation(&self) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, self.yaw, -self.pitch, 0.0)
+    }
+
+    /// Looking straight down, keeping the yaw so the map can still be rotated
+    fn top_down_rotation(&self) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, self.yaw, -FRAC_PI_2, 0.0)
+    }
+}
+
+/// Whether the main camera shows a top-down orthographic map instead of the perspective view
+#[derive(Resource, Debug, Default)]
+pub struct MapMode {
+    pub enabled: bool,
+}
+
+/// The height of the ground area shown by the map at the given zoom distance. It matches what
+/// the default perspective projection shows at that distance, so toggling doesn't jump.
+fn map_scale(distance: f32) -> f32 {
+    2.0 * distance * f32::tan(FRAC_PI_8)
+}
+
+#[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug, Reflect)]
+pub enum CameraAction {
+    Pan,
+    ZoomIn,
+    ZoomOut,
+    RotateLeft,
+    RotateRight,
+    FocusNext,
+    FocusPrevious,
+    FitAll,
+    ToggleMap,
+}
+
+impl CameraAction {
+    /// The bindings used until the player changes them through the `InputMap<CameraAction>` resource
+    pub fn default_input_map() -> InputMap<CameraAction> {
+        use CameraAction::*;
+
+        let mut input_map = InputMap::default();
+        input_map
+            .insert(VirtualDPad::wasd(), Pan)
+            .insert(VirtualDPad::arrow_keys(), Pan)
+            .insert(DualAxis::left_stick(), Pan)
+            .insert(KeyCode::Equals, ZoomIn)
+            .insert(KeyCode::NumpadAdd, ZoomIn)
+            .insert(GamepadButtonType::RightTrigger2, ZoomIn)
+            .insert(KeyCode::Minus, ZoomOut)
+            .insert(KeyCode::NumpadSubtract, ZoomOut)
+            .insert(GamepadButtonType::LeftTrigger2, ZoomOut)
+            .insert(KeyCode::Q, RotateLeft)
+            .insert(GamepadButtonType::LeftTrigger, RotateLeft)
+            .insert(KeyCode::E, RotateRight)
+            .insert(GamepadButtonType::RightTrigger, RotateRight)
+            .insert(KeyCode::Tab, FocusNext)
+            .insert(GamepadButtonType::East, FocusNext)
+            .insert_modified(Modifier::Shift, KeyCode::Tab, FocusPrevious)
+            .insert(GamepadButtonType::West, FocusPrevious)
+            .insert(KeyCode::F, FitAll)
+            .insert(GamepadButtonType::North, FitAll)
+            .insert(KeyCode::M, ToggleMap)
+            .insert(GamepadButtonType::Select, ToggleMap);
+        input_map
+    }
 }
 
 fn setup_camera(mut commands: Commands) {
+    let transform = Transform::from_xyz(3.0, 15.0, -16.0).looking_at(Vec3::ZERO, Vec3::Y);
     commands.spawn((
         Camera3dBundle {
-            transform: Transform::from_xyz(3.0, 15.0, -16.0).looking_at(Vec3::ZERO, Vec3::Y),
+            transform,
             ..Default::default()
         },
         MainCamera,
         CameraZoom::default(),
         CameraFocus::default(),
-        bevy_mod_picking::prelude::RaycastPickCamera::default(),
+        CameraOrientation::from_rotation(transform.rotation),
+        RaycastPickCamera::default(),
     ));
 }
 
+/// Moves the camera towards its focus and zoom. The smoothing decays exponentially,
+/// so it looks the same regardless of frame rate.
 fn follow_target(
-    mut query: Query<(&mut Transform, &CameraZoom, &CameraFocus), With<MainCamera>>,
+    mut query: Query<
+        (
+            &mut Transform,
+            &mut CameraZoom,
+            &mut Projection,
+            &CameraFocus,
+            &CameraOrientation,
+        ),
+        With<MainCamera>,
+    >,
+    map_mode: Res<MapMode>,
     time: Res<Time>,
 ) {
-    let Ok((mut transform, zoom, focus)) = query.get_single_mut() else {
+    // Rates of the exponential decay, per second
+    const FOLLOW_SPEED: f32 = 3.0;
+    const ZOOM_SPEED: f32 = 8.0;
+
+    let Ok((mut transform, mut zoom, mut projection, focus, orientation)) = query.get_single_mut()
+    else {
         return;
     };
+    let dt = time.delta_seconds();
+
+    transform.rotation = match map_mode.enabled {
+        true => orientation.top_down_rotation(),
+        false => orientation.rotation(),
+    };
+
+    let zoom_t = 1.0 - f32::exp(-ZOOM_SPEED * dt);
+    zoom.current_distance += (zoom.distance - zoom.current_distance) * zoom_t;
+    if let Projection::Orthographic(ref mut orthographic) = *projection {
+        orthographic.scale = map_scale(zoom.current_distance);
+    }
 
     let current_position = transform.translation;
-    let desired_position = focus.target - transform.forward() * zoom.distance;
-    let translation = (desired_position - current_position) * time.delta_seconds();
+    let desired_position = focus.target - transform.forward() * zoom.current_distance;
+    let follow_t = 1.0 - f32::exp(-FOLLOW_SPEED * dt);
 
-    transform.translation += translation;
+    transform.translation = current_position.lerp(desired_position, follow_t);
 }
 
 fn zoom(
@@ -67,9 +246,291 @@ fn zoom(
     }
 }
 
+fn handle_camera_actions(
+    mut query: Query<
+        (
+            &Transform,
+            &mut CameraZoom,
+            &mut CameraFocus,
+            &mut CameraOrientation,
+        ),
+        With<MainCamera>,
+    >,
+    action_state: Res<ActionState<CameraAction>>,
+    time: Res<Time>,
+) {
+    // In multiples of the zoom distance, per second
+    const PAN_SPEED: f32 = 1.0;
+    // In world units per second
+    const ZOOM_SPEED: f32 = 20.0;
+    // In radians per second
+    const ROTATE_SPEED: f32 = 2.0;
+
+    let Ok((transform, mut zoom, mut focus, mut orientation)) = query.get_single_mut() else {
+        return;
+    };
+    let dt = time.delta_seconds();
+
+    if let Some(pan) = action_state.axis_pair(CameraAction::Pan) {
+        let pan = pan.xy();
+        if pan != Vec2::ZERO {
+            let forward = transform.forward();
+            let forward = Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
+            let right = transform.right();
+            let right = Vec3::new(right.x, 0.0, right.z).normalize_or_zero();
+
+            focus.target += (right * pan.x + forward * pan.y) * PAN_SPEED * zoom.distance * dt;
+            focus.entity = None;
+        }
+    }
+
+    let mut zoom_direction = 0.0;
+    if action_state.pressed(CameraAction::ZoomIn) {
+        zoom_direction -= 1.0;
+    }
+    if action_state.pressed(CameraAction::ZoomOut) {
+        zoom_direction += 1.0;
+    }
+    if zoom_direction != 0.0 {
+        zoom.distance = f32::clamp(
+            zoom.distance + zoom_direction * ZOOM_SPEED * dt,
+            zoom.min_dist,
+            zoom.max_dist,
+        );
+    }
+
+    if action_state.pressed(CameraAction::RotateLeft) {
+        orientation.yaw += ROTATE_SPEED * dt;
+    }
+    if action_state.pressed(CameraAction::RotateRight) {
+        orientation.yaw -= ROTATE_SPEED * dt;
+    }
+}
+
+#[derive(Default, PartialEq)]
+enum MouseDrag {
+    #[default]
+    None,
+    Pan,
+    Orbit,
+}
+
+/// Dragging with the left button on empty space pans, dragging with the right button orbits
+#[allow(clippy::too_many_arguments)]
+fn mouse_drag_camera(
+    mut query: Query<
+        (
+            &Transform,
+            &CameraZoom,
+            &mut CameraFocus,
+            &mut CameraOrientation,
+        ),
+        With<MainCamera>,
+    >,
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<PrimaryWindow>>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut motion: EventReader<MouseMotion>,
+    mut pointer_down: EventReader<Pointer<Down>>,
+    map_mode: Res<MapMode>,
+    mut drag: Local<MouseDrag>,
+) {
+    // Fraction of the zoom distance moved per pixel
+    const PAN_SENSITIVITY: f32 = 0.0015;
+    // Radians per pixel
+    const ORBIT_SENSITIVITY: f32 = 0.005;
+
+    // Pressing a button over a node or link should interact with it instead
+    let pressed_on_entity = pointer_down.iter().count() > 0;
+    let pressed_on_ui = main_window_query
+        .get_single()
+        .ok()
+        .and_then(|window| contexts.try_ctx_for_window_mut(window))
+        .is_some_and(|ctx| ctx.is_pointer_over_area());
+
+    // Shift-dragging draws a selection box instead
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if !pressed_on_entity && !pressed_on_ui {
+        if buttons.just_pressed(MouseButton::Left) && !shift {
+            *drag = MouseDrag::Pan;
+        } else if buttons.just_pressed(MouseButton::Right) {
+            *drag = MouseDrag::Orbit;
+        }
+    }
+
+    match *drag {
+        MouseDrag::Pan if !buttons.pressed(MouseButton::Left) => *drag = MouseDrag::None,
+        MouseDrag::Orbit if !buttons.pressed(MouseButton::Right) => *drag = MouseDrag::None,
+        _ => {}
+    }
+
+    let delta: Vec2 = motion.iter().map(|ev| ev.delta).sum();
+    if *drag == MouseDrag::None || delta == Vec2::ZERO {
+        return;
+    }
+
+    let Ok((transform, zoom, mut focus, mut orientation)) = query.get_single_mut() else {
+        return;
+    };
+
+    match *drag {
+        MouseDrag::Pan => {
+            let forward = transform.forward();
+            let forward = Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
+            let right = transform.right();
+            let right = Vec3::new(right.x, 0.0, right.z).normalize_or_zero();
+
+            // Move the focus opposite to the mouse, so the ground follows the cursor
+            focus.target += (forward * delta.y - right * delta.x) * PAN_SENSITIVITY * zoom.distance;
+            focus.entity = None;
+        }
+        MouseDrag::Orbit => {
+            orientation.yaw -= delta.x * ORBIT_SENSITIVITY;
+            // The map always looks straight down
+            if !map_mode.enabled {
+                orientation.add_pitch(delta.y * ORBIT_SENSITIVITY);
+            }
+        }
+        MouseDrag::None => {}
+    }
+}
+
+/// Cycles the focus through the visible nodes
+fn focus_next_node(
+    camera_query: Query<&CameraFocus, With<MainCamera>>,
+    node_query: Query<(Entity, &Node)>,
+    action_state: Res<ActionState<CameraAction>>,
+    mut writer: EventWriter<SetTarget>,
+) {
+    let step: isize = if action_state.just_pressed(CameraAction::FocusPrevious) {
+        -1
+    } else if action_state.just_pressed(CameraAction::FocusNext) {
+        1
+    } else {
+        return;
+    };
+
+    let Ok(focus) = camera_query.get_single() else {
+        return;
+    };
+
+    let mut nodes: Vec<Entity> = node_query
+        .iter()
+        .filter(|(_, node)| node.visible)
+        .map(|(e, _)| e)
+        .collect();
+    if nodes.is_empty() {
+        return;
+    }
+    nodes.sort();
+
+    let next = match focus
+        .entity
+        .and_then(|e| nodes.iter().position(|n| *n == e))
+    {
+        Some(i) => (i as isize + step).rem_euclid(nodes.len() as isize) as usize,
+        None if step > 0 => 0,
+        None => nodes.len() - 1,
+    };
+
+    writer.send(SetTarget(nodes[next]));
+}
+
+fn toggle_map_mode(action_state: Res<ActionState<CameraAction>>, mut map_mode: ResMut<MapMode>) {
+    if action_state.just_pressed(CameraAction::ToggleMap) {
+        map_mode.enabled = !map_mode.enabled;
+    }
+}
+
+fn apply_map_mode(
+    map_mode: Res<MapMode>,
+    mut query: Query<(&mut Projection, &CameraZoom), With<MainCamera>>,
+) {
+    if !map_mode.is_changed() {
+        return;
+    }
+
+    let Ok((mut projection, zoom)) = query.get_single_mut() else {
+        return;
+    };
+
+    *projection = match map_mode.enabled {
+        true => Projection::Orthographic(OrthographicProjection {
+            scale: map_scale(zoom.current_distance),
+            scaling_mode: ScalingMode::FixedVertical(1.0),
+            ..Default::default()
+        }),
+        false => Projection::Perspective(PerspectiveProjection::default()),
+    };
+}
+
 #[derive(Event)]
 pub struct SetTarget(pub Entity);
 
+/// Moves the camera so every visible node is on screen
+#[derive(Event)]
+pub struct FitAll;
+
+fn send_fit_all(action_state: Res<ActionState<CameraAction>>, mut writer: EventWriter<FitAll>) {
+    if action_state.just_pressed(CameraAction::FitAll) {
+        writer.send(FitAll);
+    }
+}
+
+fn fit_all(
+    mut camera_query: Query<(&mut CameraFocus, &mut CameraZoom, &Projection), With<MainCamera>>,
+    node_query: Query<(&Transform, &Node)>,
+    mut events: EventReader<FitAll>,
+) {
+    // Extra room around the outermost nodes, in world units
+    const MARGIN: f32 = 2.0;
+
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    let Ok((mut focus, mut zoom, projection)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    let positions: Vec<Vec3> = node_query
+        .iter()
+        .filter(|(_, node)| node.visible)
+        .map(|(transform, _)| transform.translation)
+        .collect();
+    if positions.is_empty() {
+        return;
+    }
+
+    let min = positions.iter().copied().reduce(Vec3::min).unwrap();
+    let max = positions.iter().copied().reduce(Vec3::max).unwrap();
+    let center = (min + max) / 2.0;
+    let radius = positions
+        .iter()
+        .map(|p| p.distance(center))
+        .fold(0.0, f32::max)
+        + MARGIN;
+
+    // The narrower of the two directions decides how far back the camera has to be
+    let distance = match projection {
+        Projection::Perspective(p) => {
+            let half_horizontal = f32::atan(f32::tan(p.fov / 2.0) * p.aspect_ratio);
+            radius / f32::sin(f32::min(p.fov / 2.0, half_horizontal))
+        }
+        Projection::Orthographic(o) => {
+            let aspect_ratio = o.area.width() / o.area.height();
+            2.0 * radius / map_scale(1.0) / f32::min(aspect_ratio, 1.0)
+        }
+    };
+
+    focus.target = center;
+    focus.entity = None;
+    // Deliberately not clamped to `max_dist`, so even very large graphs fit
+    zoom.distance = f32::max(distance, zoom.min_dist);
+}
+
 fn set_target(
     mut main_camera_query: Query<&mut CameraFocus, With<MainCamera>>,
     other_query: Query<&Transform, Without<MainCamera>>,
@@ -89,6 +550,26 @@ fn set_target(
         };
 
         camera_focus.target = transform.translation;
+        camera_focus.entity = Some(*e);
+    }
+}
+
+/// Keeps the focus on the followed entity, since nodes move around as the layout settles
+fn track_focused_entity(
+    mut main_camera_query: Query<&mut CameraFocus, With<MainCamera>>,
+    other_query: Query<&Transform, Without<MainCamera>>,
+) {
+    let Ok(mut camera_focus) = main_camera_query.get_single_mut() else {
+        return;
+    };
+
+    let Some(e) = camera_focus.entity else {
+        return;
+    };
+
+    match other_query.get(e) {
+        Ok(transform) => camera_focus.target = transform.translation,
+        Err(_) => camera_focus.entity = None,
     }
 }
 
@@ -97,7 +578,28 @@ pub struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SetTarget>()
+            .add_event::<FitAll>()
+            .init_resource::<MapMode>()
+            .add_plugins(InputManagerPlugin::<CameraAction>::default())
+            .init_resource::<ActionState<CameraAction>>()
+            .insert_resource(CameraAction::default_input_map())
             .add_systems(Startup, setup_camera)
-            .add_systems(Update, (follow_target, set_target, zoom));
+            .add_systems(
+                Update,
+                (
+                    handle_camera_actions.run_if(not_typing),
+                    mouse_drag_camera,
+                    focus_next_node.run_if(not_typing),
+                    send_fit_all.run_if(not_typing),
+                    fit_all,
+                    toggle_map_mode.run_if(not_typing),
+                    apply_map_mode,
+                    set_target,
+                    track_focused_entity,
+                    follow_target,
+                )
+                    .chain(),
+            )
+            .add_systems(Update, zoom);
     }
 }
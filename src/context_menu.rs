@@ -0,0 +1,303 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts, EguiSet};
+
+use crate::{
+    belt::{Belt, BeltEvent, BeltTiers},
+    camera::SetTarget,
+    ingredient::Ingredients,
+    link::{Link, LinkFlow},
+    node::{Node, NodeType},
+    recipe::{CancelRefund, RecipeEvent, RecipeIndex, Recipes},
+    save::SaveData,
+    utils,
+};
+
+/// The menu opened by right clicking a node or link
+#[derive(Resource, Debug, Default)]
+pub struct ContextMenu {
+    target: Option<Entity>,
+    /// Where the menu was opened, in logical pixels
+    position: Vec2,
+}
+
+impl ContextMenu {
+    pub fn open(&mut self, target: Entity, position: Vec2) {
+        self.target = Some(target);
+        self.position = position;
+    }
+
+    pub fn close(&mut self) {
+        self.target = None;
+    }
+
+    /// Shows the menu's contents in a popup at the position it was opened
+    fn show(&self, ctx: &egui::Context, add_contents: impl FnOnce(&mut egui::Ui)) -> egui::Rect {
+        egui::Area::new("context menu")
+            .order(egui::Order::Foreground)
+            .fixed_pos(egui::pos2(self.position.x, self.position.y))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_min_width(180.0);
+                    add_contents(ui);
+                });
+            })
+            .response
+            .rect
+    }
+}
+
+/// Menus close on Escape, or when clicking anywhere else
+fn dismissed(ctx: &egui::Context, menu_rect: egui::Rect) -> bool {
+    ctx.input(|input| {
+        let pressed_elsewhere = input.pointer.any_pressed()
+            && input
+                .pointer
+                .interact_pos()
+                .is_some_and(|pos| !menu_rect.contains(pos));
+        pressed_elsewhere || input.key_pressed(egui::Key::Escape)
+    })
+}
+
+/// Closes the menu once whatever it was opened on is gone
+fn close_stale_menu(
+    mut context_menu: ResMut<ContextMenu>,
+    node_query: Query<&Node>,
+    link_query: Query<(), With<Link>>,
+) {
+    let Some(e) = context_menu.target else {
+        return;
+    };
+    let stale = match node_query.get(e) {
+        Ok(node) => !node.visible,
+        Err(_) => !link_query.contains(e),
+    };
+    if stale {
+        context_menu.close();
+    }
+}
+
+/// The recipes whose automation the menu toggles for a node: the node's own recipe, or every
+/// automatic recipe producing the node's ingredient
+pub(crate) fn automated_recipes(ty: NodeType, recipes: &Recipes) -> Vec<RecipeIndex> {
+    recipes
+        .enumerate()
+        .filter(|(i, holder)| {
+            holder.unlocked
+                && holder.recipe.automatic
+                && match ty {
+                    NodeType::Recipe(recipe) => *i == recipe,
+                    NodeType::Ingredient(ingredient) => {
+                        holder.recipe.output.iter().any(|(o, _)| *o == ingredient)
+                    }
+                }
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_context_menu(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<PrimaryWindow>>,
+    mut context_menu: ResMut<ContextMenu>,
+    node_query: Query<&Node>,
+    mut ingredients: ResMut<Ingredients>,
+    mut recipes: ResMut<Recipes>,
+    mut save_data: ResMut<SaveData>,
+    mut writer: EventWriter<SetTarget>,
+    mut recipe_writer: EventWriter<RecipeEvent>,
+    refund: Res<CancelRefund>,
+) {
+    let Some(e) = context_menu.target else {
+        return;
+    };
+    let Ok(node) = node_query.get(e) else {
+        return;
+    };
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    let id = node.ty.id(&ingredients, &recipes).to_string();
+    let automated = automated_recipes(node.ty, &recipes);
+    // Toggling turns everything off if anything is on, so one click always stops production
+    let any_automated = automated
+        .iter()
+        .any(|i| recipes.get_recipe_holder(i).automation_enabled);
+    let pinned = save_data.watch_list.contains(&id);
+
+    let mut close = false;
+    let rect = context_menu.show(ctx, |ui| {
+        ui.strong(&id);
+        ui.separator();
+
+        if ui.button("Focus camera").clicked() {
+            writer.send(SetTarget(e));
+            close = true;
+        }
+
+        let pin_label = match pinned {
+            true => "Unpin from watch list",
+            false => "Pin to watch list",
+        };
+        if ui.button(pin_label).clicked() {
+            if pinned {
+                save_data.watch_list.retain(|pinned_id| *pinned_id != id);
+            } else {
+                save_data.watch_list.push(id.clone());
+            }
+            close = true;
+        }
+
+        if !automated.is_empty() {
+            let label = match (node.ty, any_automated) {
+                (NodeType::Recipe(_), true) => "Disable automation",
+                (NodeType::Recipe(_), false) => "Enable automation",
+                (NodeType::Ingredient(_), true) => "Disable automation of producers",
+                (NodeType::Ingredient(_), false) => "Enable automation of producers",
+            };
+            if ui.button(label).clicked() {
+                for i in automated.iter() {
+                    recipes.get_recipe_holder_mut(i).automation_enabled = !any_automated;
+                }
+                close = true;
+            }
+        }
+
+        if let NodeType::Recipe(i) = node.ty {
+            if recipes.get_recipe_holder(&i).started && ui.button(refund.button_label()).clicked() {
+                recipe_writer.send(RecipeEvent::CancelRecipe(i));
+                close = true;
+            }
+
+            let current = recipes.get_recipe_holder(&i).priority;
+            let mut priority = current;
+            ui.horizontal(|ui| {
+                ui.label("Priority")
+                    .on_hover_text("Decides who gets scarce inputs first, see the toolbar");
+                ui.add(egui::DragValue::new(&mut priority).speed(0.1));
+            });
+            if priority != current {
+                recipes.get_recipe_holder_mut(&i).priority = priority;
+            }
+        }
+
+        if let NodeType::Ingredient(i) = node.ty {
+            let current = ingredients.get(i).stock_limit;
+            let mut enabled = current.is_some();
+            let mut limit = current.unwrap_or(f64::max(ingredients.get(i).current, 10.0));
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut enabled, "Stock limit");
+                ui.add_enabled(
+                    enabled,
+                    egui::DragValue::new(&mut limit)
+                        .clamp_range(0.0..=f64::MAX)
+                        .speed(1.0),
+                );
+            });
+            // Only write when edited, so the resource isn't marked as changed every frame
+            let new = enabled.then_some(limit);
+            if new != current {
+                ingredients.get_mut(i).stock_limit = new;
+            }
+        }
+
+        if ui.button("Hide node").clicked() {
+            save_data.hidden_nodes.insert(id.clone());
+            close = true;
+        }
+    });
+
+    if close || dismissed(ctx, rect) {
+        context_menu.close();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_link_menu(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<PrimaryWindow>>,
+    mut context_menu: ResMut<ContextMenu>,
+    link_query: Query<(&LinkFlow, &Belt)>,
+    tiers: Res<BeltTiers>,
+    ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    mut writer: EventWriter<BeltEvent>,
+) {
+    let Some(e) = context_menu.target else {
+        return;
+    };
+    let Ok((flow, belt)) = link_query.get(e) else {
+        return;
+    };
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    let mut close = false;
+    let rect = context_menu.show(ctx, |ui| {
+        ui.strong(format!(
+            "{} to {}",
+            recipes.get_recipe(&flow.recipe).id,
+            ingredients.get(flow.ingredient).name
+        ));
+        ui.separator();
+
+        let Some(current) = tiers.get(belt.tier) else {
+            ui.label("No belt, carries any amount");
+            return;
+        };
+        ui.label(format!(
+            "{}: {} of {}",
+            current.name,
+            utils::format_rate(flow.throughput),
+            utils::format_rate(current.capacity)
+        ));
+
+        let Some(next) = tiers.get(belt.tier + 1) else {
+            ui.weak("Fastest belt already");
+            return;
+        };
+        let mut label = format!(
+            "Upgrade to {} ({})",
+            next.name,
+            utils::format_rate(next.capacity)
+        );
+        for (ty, q) in next.cost.iter() {
+            label.push_str(", ");
+            utils::write_format_quantity(&mut label, *q).unwrap();
+            label.push(' ');
+            label.push_str(&ingredients.get(*ty).name);
+        }
+        if ui
+            .add_enabled(next.can_afford(&ingredients), egui::Button::new(label))
+            .clicked()
+        {
+            writer.send(BeltEvent::Upgrade(e));
+            close = true;
+        }
+    });
+
+    if close || dismissed(ctx, rect) {
+        context_menu.close();
+    }
+}
+
+pub struct ContextMenuPlugin;
+
+impl Plugin for ContextMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ContextMenu>().add_systems(
+            PostUpdate,
+            (close_stale_menu, (draw_context_menu, draw_link_menu))
+                .chain()
+                .after(EguiSet::InitContexts),
+        );
+    }
+}
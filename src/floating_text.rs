@@ -3,8 +3,10 @@ use bevy::prelude::*;
 use crate::{
     camera::MainCamera,
     ingredient::Ingredients,
-    node::{Node, NodeRegistry},
-    recipe::{RecipeEvent, Recipes},
+    node::{Node, NodeRegistry, NodeType},
+    recipe::{RecipeCompleted, Recipes},
+    transport::{Delivery, Logistics},
+    unlock::{Unlockable, Unlocked},
     utils,
 };
 
@@ -67,29 +69,27 @@ fn position_floating_text(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn recipe_completion(
     mut commands: Commands,
-    mut reader: EventReader<RecipeEvent>,
+    mut reader: EventReader<RecipeCompleted>,
     recipes: Res<Recipes>,
     ingredients: Res<Ingredients>,
     node_registry: Res<NodeRegistry>,
     node_query: Query<&Transform, With<Node>>,
     settings: Res<FloatingTextSettings>,
+    logistics: Res<Logistics>,
 ) {
-    if !settings.enabled {
+    // With logistics the outputs only show up once they're delivered
+    if !settings.enabled || logistics.enabled {
         return;
     }
 
-    for ev in reader.into_iter() {
-        let RecipeEvent::FinishRecipe(i) = ev else {
-            // We only care about FinishRecipe events for now
-            continue;
-        };
-
+    for RecipeCompleted(i) in reader.into_iter() {
         let recipe = recipes.get_recipe(i);
 
         for (ty, amount) in recipe.output.iter() {
-            let Some(node_e) = node_registry.get(ty) else {
+            let Some(node_e) = node_registry.get(&NodeType::Ingredient(*ty)) else {
                 warn!("No node registered for {}", ingredients.get(*ty).name);
                 continue;
             };
@@ -110,6 +110,66 @@ fn recipe_completion(
     }
 }
 
+fn delivery(
+    mut commands: Commands,
+    mut reader: EventReader<Delivery>,
+    node_registry: Res<NodeRegistry>,
+    node_query: Query<&Transform, With<Node>>,
+    settings: Res<FloatingTextSettings>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    for ev in reader.iter() {
+        let Some(node_transform) = node_registry
+            .get(&NodeType::Ingredient(ev.ingredient))
+            .and_then(|e| node_query.get(*e).ok())
+        else {
+            continue;
+        };
+
+        commands.spawn(floating_text_bundle(
+            utils::format_number(ev.amount),
+            node_transform.translation,
+        ));
+    }
+}
+
+fn discovery(
+    mut commands: Commands,
+    mut reader: EventReader<Unlocked>,
+    recipes: Res<Recipes>,
+    ingredients: Res<Ingredients>,
+    node_registry: Res<NodeRegistry>,
+    node_query: Query<&Transform, With<Node>>,
+    settings: Res<FloatingTextSettings>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    for Unlocked(target) in reader.into_iter() {
+        let (ty, name) = match *target {
+            Unlockable::Ingredient(i) => (NodeType::Ingredient(i), &ingredients.get(i).name),
+            Unlockable::Recipe(i) => (NodeType::Recipe(i), &recipes.get_recipe(&i).id),
+            // Upgrades don't have a node to show the text on
+            Unlockable::Upgrade(_) => continue,
+        };
+
+        let Some(node_transform) = node_registry.get(&ty).and_then(|e| node_query.get(*e).ok())
+        else {
+            warn!("Couldn't find the node of newly discovered {}", name);
+            continue;
+        };
+
+        commands.spawn(floating_text_bundle(
+            format!("Discovered {}!", name),
+            node_transform.translation,
+        ));
+    }
+}
+
 fn floating_text_bundle(label: String, position: Vec3) -> impl Bundle {
     (
         TextBundle {
@@ -152,7 +212,14 @@ pub struct FloatingTextPlugin;
 
 impl Plugin for FloatingTextPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<FloatingTextSettings>()
-            .add_systems(Update, (recipe_completion, position_floating_text));
+        app.init_resource::<FloatingTextSettings>().add_systems(
+            Update,
+            (
+                recipe_completion,
+                delivery,
+                discovery,
+                position_floating_text,
+            ),
+        );
     }
 }
@@ -1,8 +1,10 @@
 use bevy::{
-    prelude::{error, Color},
-    utils::HashMap,
+    prelude::{error, App, Color, Vec2},
+    utils::{HashMap, HashSet},
 };
 
+use crate::unlock::{set_unlocked, UnlockCondition, Unlockable};
+
 struct Ingredient {
     id: String,
     name: String, // TODO: localization
@@ -18,9 +20,69 @@ struct Recipe {
     automatic: bool,
 }
 
+struct Upgrade {
+    id: String,
+    name: String, // TODO: localization
+    cost: Vec<(String, f64)>,
+}
+
+struct BeltTier {
+    name: String, // TODO: localization
+    capacity: f64,
+    cost: Vec<(String, f64)>,
+}
+
+struct Research {
+    id: String,
+    name: String, // TODO: localization
+    cost: Vec<(String, f64)>,
+    duration: f64,
+    prerequisites: Vec<String>,
+    unlocks: Vec<String>,
+}
+
+struct Site {
+    id: String,
+    name: String, // TODO: localization
+    origin: Vec2,
+    recipes: Vec<String>,
+}
+
+struct ShippingRoute {
+    from: String,
+    to: String,
+    ingredient: String,
+    amount: f64,
+    travel_time: f64,
+    cost: Vec<(String, f64)>,
+}
+
+/// Something that has to happen before an ingredient, recipe or upgrade is revealed.
+/// Everything without an unlock requirement is available from the start.
+pub enum UnlockRequirement {
+    /// The ingredient with this id has been produced at least once
+    Produced(String),
+    /// The stock of the ingredient with this id has reached the amount
+    StockAtLeast(String, f64),
+    /// The upgrade with this id has been bought
+    Upgrade(String),
+}
+
+struct Unlock {
+    target: String,
+    requirement: UnlockRequirement,
+}
+
 pub struct GameBuilder {
     ingredients: Vec<Ingredient>,
     recipes: Vec<Recipe>,
+    upgrades: Vec<Upgrade>,
+    unlocks: Vec<Unlock>,
+    research: Vec<Research>,
+    belt_tiers: Vec<BeltTier>,
+    sites: Vec<Site>,
+    shipping_routes: Vec<ShippingRoute>,
+    cancel_refund: f64,
 }
 
 impl Default for GameBuilder {
@@ -28,6 +90,13 @@ impl Default for GameBuilder {
         GameBuilder {
             ingredients: vec![],
             recipes: vec![],
+            upgrades: vec![],
+            unlocks: vec![],
+            research: vec![],
+            belt_tiers: vec![],
+            sites: vec![],
+            shipping_routes: vec![],
+            cancel_refund: 1.0,
         }
     }
 }
@@ -77,28 +146,224 @@ impl GameBuilder {
         self
     }
 
-    pub fn build(self) -> (crate::ingredient::Ingredients, crate::recipe::Recipes) {
+    pub fn add_upgrade<S: Into<String>>(
+        mut self,
+        id: impl Into<String>,
+        name: impl Into<String>,
+        cost: impl IntoIterator<Item = (S, f64)>,
+    ) -> Self {
+        let upgrade = Upgrade {
+            id: id.into(),
+            name: name.into(),
+            cost: Vec::from_iter(cost.into_iter().map(|(s, q)| (s.into(), q))),
+        };
+
+        self.upgrades.push(upgrade);
+
+        self
+    }
+
+    /// Adds a belt links can be upgraded to, carrying `capacity` items per second.
+    /// Links start out with the first tier added, so its cost is never paid.
+    pub fn add_belt_tier<S: Into<String>>(
+        mut self,
+        name: impl Into<String>,
+        capacity: f64,
+        cost: impl IntoIterator<Item = (S, f64)>,
+    ) -> Self {
+        let belt_tier = BeltTier {
+            name: name.into(),
+            capacity,
+            cost: Vec::from_iter(cost.into_iter().map(|(s, q)| (s.into(), q))),
+        };
+
+        self.belt_tiers.push(belt_tier);
+
+        self
+    }
+
+    /// Adds a factory site laid out around `origin`, with its own copy of each recipe in
+    /// `recipes` and its own stock of the ingredients they use or shipping routes carry.
+    /// The first site added is the home site, whose ingredients and recipes keep their ids.
+    /// At the other sites they are prefixed with the site id, like `site_mine/ingr_coal`.
+    /// Without any sites, everything is at a single home site.
+    pub fn add_site<S: Into<String>>(
+        mut self,
+        id: impl Into<String>,
+        name: impl Into<String>,
+        origin: Vec2,
+        recipes: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let site = Site {
+            id: id.into(),
+            name: name.into(),
+            origin,
+            recipes: Vec::from_iter(recipes.into_iter().map(|s| s.into())),
+        };
+
+        self.sites.push(site);
+
+        self
+    }
+
+    /// Adds a route shipping `amount` of an ingredient from the stock of the site `from` to
+    /// the site `to`, taking `travel_time` seconds. Every shipment costs `cost`, paid from the
+    /// stock of `from`.
+    pub fn add_shipping_route<S: Into<String>>(
+        mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+        ingredient: impl Into<String>,
+        amount: f64,
+        travel_time: f64,
+        cost: impl IntoIterator<Item = (S, f64)>,
+    ) -> Self {
+        let route = ShippingRoute {
+            from: from.into(),
+            to: to.into(),
+            ingredient: ingredient.into(),
+            amount,
+            travel_time,
+            cost: Vec::from_iter(cost.into_iter().map(|(s, q)| (s.into(), q))),
+        };
+
+        self.shipping_routes.push(route);
+
+        self
+    }
+
+    /// Sets the fraction of its inputs a running recipe gives back when cancelled.
+    /// Everything is refunded unless this is called.
+    pub fn set_cancel_refund(mut self, fraction: f64) -> Self {
+        if !(0.0..=1.0).contains(&fraction) {
+            error!(
+                "The refund for cancelled recipes has to be between 0 and 1, but is {}",
+                fraction
+            );
+        }
+        self.cancel_refund = fraction.clamp(0.0, 1.0);
+
+        self
+    }
+
+    /// Keeps the ingredient, recipe or upgrade with id `target` locked until `requirement` is met.
+    /// If several requirements are added for the same target, meeting any one of them unlocks it.
+    pub fn add_unlock(mut self, target: impl Into<String>, requirement: UnlockRequirement) -> Self {
+        let unlock = Unlock {
+            target: target.into(),
+            requirement,
+        };
+
+        self.unlocks.push(unlock);
+
+        self
+    }
+
+    /// Adds a research project which consumes `cost` every second for `duration` seconds,
+    /// can only be started once all of `prerequisites` are done, and on completion unlocks
+    /// the ingredients, recipes and upgrades in `unlocks`.
+    /// Everything in `unlocks` stays locked until then.
+    pub fn add_research<S: Into<String>>(
+        mut self,
+        id: impl Into<String>,
+        name: impl Into<String>,
+        cost: impl IntoIterator<Item = (S, f64)>,
+        duration: f64,
+        prerequisites: impl IntoIterator<Item = S>,
+        unlocks: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let research = Research {
+            id: id.into(),
+            name: name.into(),
+            cost: Vec::from_iter(cost.into_iter().map(|(s, q)| (s.into(), q))),
+            duration,
+            prerequisites: Vec::from_iter(prerequisites.into_iter().map(|s| s.into())),
+            unlocks: Vec::from_iter(unlocks.into_iter().map(|s| s.into())),
+        };
+
+        self.research.push(research);
+
+        self
+    }
+
+    /// Inserts the resources describing the game into `app`
+    pub fn build(self, app: &mut App) {
+        let sites = match self.sites.is_empty() {
+            true => vec![Site {
+                id: "site_home".into(),
+                name: "Home".into(),
+                origin: Vec2::ZERO,
+                recipes: self.recipes.iter().map(|r| r.id.clone()).collect(),
+            }],
+            false => self.sites,
+        };
+
+        let mut site_map: HashMap<String, crate::site::SiteIndex> = HashMap::new();
+
+        let mut sites_resource = crate::site::Sites::default();
+        let mut site_indices = vec![];
+
+        for site in sites.iter() {
+            let ix = sites_resource.add_site(crate::site::Site {
+                name: site.name.clone(),
+                origin: site.origin,
+            });
+            site_indices.push(ix);
+            if site_map.insert(site.id.clone(), ix).is_some() {
+                error!(
+                    "Multiple sites with id {}. Only the last one added will take effect",
+                    site.id
+                );
+            }
+        }
+
+        // The id of the copy of an ingredient or recipe at the nth site
+        let site_id = |n: usize, id: &str| match n {
+            0 => id.to_string(),
+            _ => format!("{}/{}", sites[n].id, id),
+        };
+
         let mut ingredient_map: HashMap<String, crate::ingredient::IngredientIndex> =
             HashMap::new();
 
         let mut ingredients_resource = crate::ingredient::Ingredients::default();
 
-        for ingredient in self.ingredients {
-            let new_ingr = crate::ingredient::Ingredient {
-                capacity: ingredient.cap.map(|q| q.into()),
-                color: ingredient.color,
-                name: ingredient.name,
-                ..Default::default()
-            };
-            let ix = ingredients_resource.add_ingredient(new_ingr);
-            let id = ingredient.id.clone();
-            match ingredient_map.insert(ingredient.id, ix) {
-                None => {} // We're good
-                Some(_) => {
-                    error!(
-                        "Multiple ingredients with id {}. Only the last one added will take effect",
-                        id
-                    );
+        for (n, (site, site_ix)) in sites.iter().zip(site_indices.iter()).enumerate() {
+            // The home site has everything, since upgrades, research and belts are paid from
+            // there. Other sites only get the ingredients they make, use or ship.
+            let used =
+                (n > 0).then(|| site_ingredients(site, &self.recipes, &self.shipping_routes));
+            for ingredient in self.ingredients.iter() {
+                if used
+                    .as_ref()
+                    .is_some_and(|used| !used.contains(ingredient.id.as_str()))
+                {
+                    continue;
+                }
+
+                let id = site_id(n, &ingredient.id);
+                // Copies at other sites are told apart by the site's name
+                let name = match n {
+                    0 => ingredient.name.clone(),
+                    _ => format!("{} ({})", ingredient.name, site.name),
+                };
+                let new_ingr = crate::ingredient::Ingredient {
+                    id: id.clone(),
+                    capacity: ingredient.cap.map(|q| q.into()),
+                    color: ingredient.color,
+                    name,
+                    site: *site_ix,
+                    ..Default::default()
+                };
+                let ix = ingredients_resource.add_ingredient(new_ingr);
+                match ingredient_map.insert(id.clone(), ix) {
+                    None => {} // We're good
+                    Some(_) => {
+                        error!(
+                            "Multiple ingredients with id {}. Only the last one added will take effect",
+                            id
+                        );
+                    }
                 }
             }
         }
@@ -107,53 +372,392 @@ impl GameBuilder {
 
         let mut recipes_resource = crate::recipe::Recipes::default();
 
-        for recipe in self.recipes {
-            let input = recipe.input.iter().filter_map(|(s, q)| {
-                match ingredient_map.get(s) {
-                    None => {
-                        error!("Recipe {} refers to ingredient {}, but that ingredient was not registered", recipe.id, s);
-                        None
-                    }
-                    Some(ix) => {
-                        Some((*ix, crate::quantity::Quantity::new(*q)))
+        for (n, (site, site_ix)) in sites.iter().zip(site_indices.iter()).enumerate() {
+            for recipe_id in site.recipes.iter() {
+                let Some(recipe) = self.recipes.iter().find(|r| r.id == *recipe_id) else {
+                    error!(
+                        "Site {} has recipe {}, but that recipe was not registered",
+                        site.id, recipe_id
+                    );
+                    continue;
+                };
+                let id = site_id(n, &recipe.id);
+
+                // Recipes use the ingredients of their own site
+                let resolve = |list: &[(String, f64)]| -> Vec<_> {
+                    list.iter()
+                        .filter_map(|(s, q)| match ingredient_map.get(&site_id(n, s)) {
+                            None => {
+                                error!(
+                                    "Recipe {} refers to ingredient {}, but that ingredient was not registered",
+                                    recipe.id, s
+                                );
+                                None
+                            }
+                            Some(ix) => Some((*ix, crate::quantity::Quantity::new(*q))),
+                        })
+                        .collect()
+                };
+
+                let new_recipe = crate::recipe::Recipe {
+                    id: id.clone(),
+                    automatic: recipe.automatic,
+                    delay: recipe.delay.into(),
+                    input: resolve(&recipe.input),
+                    output: resolve(&recipe.output),
+                    site: *site_ix,
+                };
+
+                let ix = recipes_resource.add_recipe(new_recipe);
+
+                match recipe_map.insert(id.clone(), ix) {
+                    None => {} // We're good
+                    Some(_) => {
+                        error!(
+                            "Multiple recipes with id {}. Only the last one added will take effect",
+                            id
+                        );
                     }
                 }
-            }).collect();
+            }
+        }
+
+        let mut upgrade_map: HashMap<String, crate::upgrade::UpgradeIndex> = HashMap::new();
 
-            let output = recipe.output.iter().filter_map(|(s, q)| {
-                match ingredient_map.get(s) {
+        let mut upgrades_resource = crate::upgrade::Upgrades::default();
+
+        for upgrade in self.upgrades {
+            let cost = upgrade
+                .cost
+                .iter()
+                .filter_map(|(s, q)| match ingredient_map.get(s) {
                     None => {
-                        error!("Recipe {} refers to ingredient {}, but that ingredient was not registered", recipe.id, s);
+                        error!(
+                            "Upgrade {} refers to ingredient {}, but that ingredient was not registered",
+                            upgrade.id, s
+                        );
                         None
                     }
-                    Some(ix) => {
-                        Some((*ix, crate::quantity::Quantity::new(*q)))
-                    }
-                }
-            }).collect();
-
-            let new_recipe = crate::recipe::Recipe {
-                id: recipe.id.clone(),
-                automatic: recipe.automatic,
-                delay: recipe.delay.into(),
-                input,
-                output,
+                    Some(ix) => Some((*ix, crate::quantity::Quantity::new(*q))),
+                })
+                .collect();
+
+            let new_upgrade = crate::upgrade::Upgrade {
+                id: upgrade.id.clone(),
+                name: upgrade.name,
+                cost,
+                purchased: false,
+                unlocked: true,
             };
 
-            let ix = recipes_resource.add_recipe(new_recipe);
+            let ix = upgrades_resource.add_upgrade(new_upgrade);
 
-            let id = recipe.id.clone();
-            match recipe_map.insert(recipe.id, ix) {
+            let id = upgrade.id.clone();
+            match upgrade_map.insert(upgrade.id, ix) {
                 None => {} // We're good
                 Some(_) => {
                     error!(
-                        "Multiple recipes with id {}. Only the last one added will take effect",
+                        "Multiple upgrades with id {}. Only the last one added will take effect",
                         id
                     );
                 }
             }
         }
 
-        (ingredients_resource, recipes_resource)
+        // Unlocking an ingredient or recipe unlocks its copies at every site
+        let site_copies = |id: &str| -> Vec<Unlockable> {
+            (0..sites.len())
+                .filter_map(|n| {
+                    find_unlockable(&site_id(n, id), &ingredient_map, &recipe_map, &upgrade_map)
+                })
+                .collect()
+        };
+
+        let mut unlocks_resource = crate::unlock::Unlocks::default();
+
+        for unlock in self.unlocks {
+            let condition = match unlock.requirement {
+                UnlockRequirement::Produced(s) => ingredient_map
+                    .get(&s)
+                    .map(|ix| UnlockCondition::Produced(*ix)),
+                UnlockRequirement::StockAtLeast(s, q) => ingredient_map
+                    .get(&s)
+                    .map(|ix| UnlockCondition::StockAtLeast(*ix, q)),
+                UnlockRequirement::Upgrade(s) => {
+                    upgrade_map.get(&s).map(|ix| UnlockCondition::Upgrade(*ix))
+                }
+            };
+
+            let Some(condition) = condition else {
+                error!(
+                    "The unlock requirement of {} refers to something that was not registered",
+                    unlock.target
+                );
+                continue;
+            };
+
+            let targets = site_copies(&unlock.target);
+            if targets.is_empty() {
+                error!(
+                    "Tried to add an unlock requirement to {}, but nothing with that id was registered",
+                    unlock.target
+                );
+                continue;
+            }
+
+            for target in targets {
+                set_unlocked(
+                    target,
+                    false,
+                    &mut ingredients_resource,
+                    &mut recipes_resource,
+                    &mut upgrades_resource,
+                );
+                unlocks_resource.add_unlock(target, condition);
+            }
+        }
+
+        // Research can require projects registered after it, so prerequisites are resolved
+        // to positions in `self.research` first
+        let mut research_map: HashMap<String, usize> = HashMap::new();
+
+        for (i, research) in self.research.iter().enumerate() {
+            if research_map.contains_key(&research.id) {
+                error!(
+                    "Multiple research projects with id {}. Only the first one added will be used as a prerequisite",
+                    research.id
+                );
+                continue;
+            }
+            research_map.insert(research.id.clone(), i);
+        }
+
+        let mut prerequisites: Vec<Vec<usize>> = self
+            .research
+            .iter()
+            .map(|research| {
+                research
+                    .prerequisites
+                    .iter()
+                    .filter_map(|s| match research_map.get(s) {
+                        None => {
+                            error!(
+                                "Research {} requires research {}, but that research was not registered",
+                                research.id, s
+                            );
+                            None
+                        }
+                        Some(ix) => Some(*ix),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // Research with circular prerequisites could never be started, so break any cycles
+        while let Some(cycle) = crate::research::find_cycle(&prerequisites) {
+            let ids: Vec<&str> = cycle
+                .iter()
+                .map(|i| self.research[*i].id.as_str())
+                .collect();
+            error!(
+                "Research prerequisites form a cycle: {}. Ignoring the requirement of {} on {}",
+                ids.join(" -> "),
+                ids[ids.len() - 1],
+                ids[0]
+            );
+
+            let last = cycle[cycle.len() - 1];
+            prerequisites[last].retain(|p| *p != cycle[0]);
+        }
+
+        let mut researches_resource = crate::research::Researches::default();
+        let mut research_indices = vec![];
+
+        for research in self.research {
+            let cost = research
+                .cost
+                .iter()
+                .filter_map(|(s, q)| match ingredient_map.get(s) {
+                    None => {
+                        error!(
+                            "Research {} refers to ingredient {}, but that ingredient was not registered",
+                            research.id, s
+                        );
+                        None
+                    }
+                    Some(ix) => Some((*ix, crate::quantity::Quantity::new(*q))),
+                })
+                .collect();
+
+            let unlocks = research
+                .unlocks
+                .iter()
+                .flat_map(|s| {
+                    let targets = site_copies(s);
+                    if targets.is_empty() {
+                        error!(
+                            "Research {} unlocks {}, but nothing with that id was registered",
+                            research.id, s
+                        );
+                    }
+                    for target in targets.iter() {
+                        set_unlocked(
+                            *target,
+                            false,
+                            &mut ingredients_resource,
+                            &mut recipes_resource,
+                            &mut upgrades_resource,
+                        );
+                    }
+                    targets
+                })
+                .collect();
+
+            let ix = researches_resource.add_research(crate::research::Research {
+                id: research.id,
+                name: research.name,
+                cost,
+                duration: research.duration.into(),
+                prerequisites: vec![],
+                unlocks,
+                progress: 0.0,
+                completed: false,
+            });
+            research_indices.push(ix);
+        }
+
+        for (ix, prerequisites) in research_indices.iter().zip(prerequisites) {
+            researches_resource.get_mut(*ix).prerequisites =
+                prerequisites.iter().map(|p| research_indices[*p]).collect();
+        }
+
+        let mut belt_tiers_resource = crate::belt::BeltTiers::default();
+
+        for belt_tier in self.belt_tiers {
+            let cost = belt_tier
+                .cost
+                .iter()
+                .filter_map(|(s, q)| match ingredient_map.get(s) {
+                    None => {
+                        error!(
+                            "Belt tier {} refers to ingredient {}, but that ingredient was not registered",
+                            belt_tier.name, s
+                        );
+                        None
+                    }
+                    Some(ix) => Some((*ix, crate::quantity::Quantity::new(*q))),
+                })
+                .collect();
+
+            belt_tiers_resource.add_tier(crate::belt::BeltTier {
+                name: belt_tier.name,
+                capacity: belt_tier.capacity,
+                cost,
+            });
+        }
+
+        let mut shipping_routes_resource = crate::site::ShippingRoutes::default();
+
+        for route in self.shipping_routes {
+            let position = |id: &str| sites.iter().position(|site| site.id == id);
+            let (Some(from), Some(to)) = (position(&route.from), position(&route.to)) else {
+                error!(
+                    "Shipping route from {} to {} refers to a site that was not registered",
+                    route.from, route.to
+                );
+                continue;
+            };
+
+            // Everything the route takes is taken at the site it starts from
+            let (Some(source), Some(destination)) = (
+                ingredient_map.get(&site_id(from, &route.ingredient)),
+                ingredient_map.get(&site_id(to, &route.ingredient)),
+            ) else {
+                error!(
+                    "Shipping route from {} to {} refers to ingredient {}, but that ingredient was not registered",
+                    route.from, route.to, route.ingredient
+                );
+                continue;
+            };
+
+            let cost = route
+                .cost
+                .iter()
+                .filter_map(|(s, q)| match ingredient_map.get(&site_id(from, s)) {
+                    None => {
+                        error!(
+                            "Shipping route from {} to {} refers to ingredient {}, but that ingredient was not registered",
+                            route.from, route.to, s
+                        );
+                        None
+                    }
+                    Some(ix) => Some((*ix, crate::quantity::Quantity::new(*q))),
+                })
+                .collect();
+
+            shipping_routes_resource.add_route(crate::site::ShippingRoute {
+                from: site_indices[from],
+                to: site_indices[to],
+                source: *source,
+                destination: *destination,
+                amount: route.amount.into(),
+                travel_time: route.travel_time.into(),
+                cost,
+                automatic: false,
+                remaining: None,
+            });
+        }
+
+        app.insert_resource(ingredients_resource)
+            .insert_resource(recipes_resource)
+            .insert_resource(upgrades_resource)
+            .insert_resource(unlocks_resource)
+            .insert_resource(researches_resource)
+            .insert_resource(belt_tiers_resource)
+            .insert_resource(sites_resource)
+            .insert_resource(shipping_routes_resource)
+            .insert_resource(crate::recipe::CancelRefund {
+                fraction: self.cancel_refund,
+            });
+    }
+}
+
+/// Ids of the ingredients used by the recipes of `site`, or shipped to or from it
+fn site_ingredients<'a>(
+    site: &'a Site,
+    recipes: &'a [Recipe],
+    routes: &'a [ShippingRoute],
+) -> HashSet<&'a str> {
+    let mut used = HashSet::new();
+    for recipe in recipes.iter().filter(|r| site.recipes.contains(&r.id)) {
+        for (s, _) in recipe.input.iter().chain(recipe.output.iter()) {
+            used.insert(s.as_str());
+        }
+    }
+    for route in routes.iter() {
+        if route.from == site.id {
+            used.insert(route.ingredient.as_str());
+            for (s, _) in route.cost.iter() {
+                used.insert(s.as_str());
+            }
+        } else if route.to == site.id {
+            used.insert(route.ingredient.as_str());
+        }
+    }
+    used
+}
+
+fn find_unlockable(
+    id: &str,
+    ingredient_map: &HashMap<String, crate::ingredient::IngredientIndex>,
+    recipe_map: &HashMap<String, crate::recipe::RecipeIndex>,
+    upgrade_map: &HashMap<String, crate::upgrade::UpgradeIndex>,
+) -> Option<Unlockable> {
+    if let Some(ix) = ingredient_map.get(id) {
+        Some(Unlockable::Ingredient(*ix))
+    } else if let Some(ix) = recipe_map.get(id) {
+        Some(Unlockable::Recipe(*ix))
+    } else {
+        upgrade_map.get(id).map(|ix| Unlockable::Upgrade(*ix))
     }
 }
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::quantity::Quantity;
+use crate::{quantity::Quantity, site::SiteIndex};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct IngredientIndex(usize);
@@ -13,32 +13,60 @@ impl IngredientIndex {
 
 #[derive(Debug)]
 pub struct Ingredient {
+    pub id: String,
     pub name: String,
     pub color: Color,
     pub current: f64,
     pub capacity: Option<Quantity>,
+    /// Everything ever produced, including anything that didn't fit under the cap
+    pub total_produced: f64,
+    pub unlocked: bool,
+    /// Automatic recipes producing this ingredient don't start while the stock is at this limit
+    pub stock_limit: Option<f64>,
+    /// Every site keeps its own stock, so each has its own copy of the ingredient
+    pub site: SiteIndex,
+    /// Recipes never take the stock below this, so it's kept for other uses
+    pub keep: Option<f64>,
 }
 
 impl Ingredient {
-    pub fn add_ingredient(&mut self, amount: f64) {
+    /// Adds to the stock up to the cap, returning whatever didn't fit
+    pub fn add_ingredient(&mut self, amount: f64) -> f64 {
+        let before = self.current;
         match self.capacity {
             None => self.current += amount,
             Some(cap) => self.current = f64::min(cap.value(), self.current + amount),
         }
+        f64::max(0.0, amount - (self.current - before))
     }
 
     pub fn spend_ingredient(&mut self, amount: f64) {
         self.current = f64::max(0.0, self.current - amount);
     }
+
+    /// Stock recipes may take, which is everything above the keep threshold
+    pub fn available(&self) -> f64 {
+        f64::max(0.0, self.current - self.keep.unwrap_or(0.0))
+    }
+
+    pub fn at_stock_limit(&self) -> bool {
+        self.stock_limit.is_some_and(|limit| self.current >= limit)
+    }
 }
 
 impl Default for Ingredient {
     fn default() -> Self {
         Ingredient {
+            id: String::new(),
             name: String::new(),
             color: Color::WHITE,
             current: 0.0,
             capacity: None,
+            total_produced: 0.0,
+            unlocked: true,
+            stock_limit: None,
+            site: SiteIndex::default(),
+            keep: None,
         }
     }
 }
@@ -0,0 +1,184 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{ingredient::Ingredients, link::Link, node::Node, recipe::Recipes, site::Sites};
+
+/// Position of a node on the ground plane as decided by the layout.
+/// The node's `Transform` is animated towards it.
+#[derive(Component, Debug)]
+pub struct LayoutNode {
+    pub position: Vec2,
+    /// Pinned nodes still push other nodes away, but are never moved by the layout
+    pub pinned: bool,
+    /// Origin of the node's site, which gravity pulls it towards
+    pub anchor: Vec2,
+}
+
+#[derive(Resource, Debug)]
+pub struct LayoutSettings {
+    /// Distance the layout tries to keep between linked nodes
+    pub edge_length: f32,
+    /// Strength of the pull towards the site's origin, which keeps unconnected nodes from drifting off
+    pub gravity: f32,
+    /// Factor the temperature is multiplied by after every step
+    pub cooling: f32,
+    pub min_temperature: f32,
+    pub max_temperature: f32,
+}
+
+impl Default for LayoutSettings {
+    fn default() -> Self {
+        LayoutSettings {
+            edge_length: 3.0,
+            gravity: 0.05,
+            cooling: 0.98,
+            min_temperature: 0.001,
+            max_temperature: 0.5,
+        }
+    }
+}
+
+/// The temperature bounds how far a node may move in a single step.
+/// It cools down over time so the layout settles, and is reset whenever the graph changes.
+#[derive(Resource, Debug, Default)]
+pub struct LayoutState {
+    temperature: f32,
+}
+
+impl LayoutState {
+    pub fn reheat(&mut self, settings: &LayoutSettings) {
+        self.temperature = settings.max_temperature;
+    }
+}
+
+fn add_layout_nodes(
+    mut commands: Commands,
+    query: Query<(Entity, &Transform, &Node), Added<Node>>,
+    mut state: ResMut<LayoutState>,
+    settings: Res<LayoutSettings>,
+    ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    sites: Res<Sites>,
+) {
+    for (e, transform, node) in query.iter() {
+        commands.entity(e).insert(LayoutNode {
+            position: Vec2::new(transform.translation.x, transform.translation.z),
+            pinned: false,
+            anchor: sites.origin(node.ty.site(&ingredients, &recipes)),
+        });
+        state.reheat(&settings);
+    }
+}
+
+fn reheat_on_new_links(
+    query: Query<(), Added<Link>>,
+    mut state: ResMut<LayoutState>,
+    settings: Res<LayoutSettings>,
+) {
+    if !query.is_empty() {
+        state.reheat(&settings);
+    }
+}
+
+/// A single Fruchterman-Reingold step: every pair of nodes repels, linked nodes attract.
+/// Each site is laid out on its own, so only nodes of the same site repel.
+fn step_layout(
+    mut node_query: Query<(Entity, &mut LayoutNode, &Node)>,
+    link_query: Query<&Link>,
+    mut state: ResMut<LayoutState>,
+    settings: Res<LayoutSettings>,
+) {
+    if state.temperature < settings.min_temperature {
+        return;
+    }
+
+    let k = settings.edge_length;
+
+    let mut index: HashMap<Entity, usize> = HashMap::new();
+    let mut positions = vec![];
+    let mut anchors = vec![];
+    for (e, layout_node, node) in node_query.iter() {
+        if !node.visible {
+            continue;
+        }
+        index.insert(e, positions.len());
+        positions.push(layout_node.position);
+        anchors.push(layout_node.anchor);
+    }
+
+    let mut displacement = vec![Vec2::ZERO; positions.len()];
+
+    for i in 0..positions.len() {
+        for j in (i + 1)..positions.len() {
+            if anchors[i] != anchors[j] {
+                continue;
+            }
+            let delta = positions[i] - positions[j];
+            let distance = delta.length();
+            let direction = if distance > f32::EPSILON {
+                delta / distance
+            } else {
+                // Nodes on top of each other get pushed apart in an arbitrary, but stable, direction
+                Vec2::from_angle(i as f32 * 2.4)
+            };
+            let force = k * k / f32::max(distance, 0.01);
+
+            displacement[i] += direction * force;
+            displacement[j] -= direction * force;
+        }
+    }
+
+    for link in link_query.iter() {
+        let (Some(&a), Some(&b)) = (index.get(&link.from), index.get(&link.to)) else {
+            continue;
+        };
+        let delta = positions[b] - positions[a];
+        let distance = delta.length();
+        if distance <= f32::EPSILON {
+            continue;
+        }
+        let force = distance * distance / k;
+
+        displacement[a] += delta / distance * force;
+        displacement[b] -= delta / distance * force;
+    }
+
+    for (e, mut layout_node, _) in node_query.iter_mut() {
+        let Some(&i) = index.get(&e) else {
+            continue;
+        };
+        if layout_node.pinned {
+            continue;
+        }
+
+        let d = displacement[i] - (positions[i] - anchors[i]) * settings.gravity;
+        let length = d.length();
+        if length > f32::EPSILON {
+            layout_node.position += d / length * f32::min(length, state.temperature);
+        }
+    }
+
+    state.temperature *= settings.cooling;
+}
+
+fn move_nodes(mut query: Query<(&mut Transform, &LayoutNode)>, time: Res<Time>) {
+    const MOVE_SPEED: f32 = 5.0;
+    let t = 1.0 - f32::exp(-MOVE_SPEED * time.delta_seconds());
+
+    for (mut transform, layout_node) in query.iter_mut() {
+        let current = Vec2::new(transform.translation.x, transform.translation.z);
+        let new = current.lerp(layout_node.position, t);
+        transform.translation.x = new.x;
+        transform.translation.z = new.y;
+    }
+}
+
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LayoutSettings>()
+            .init_resource::<LayoutState>()
+            .add_systems(Update, (add_layout_nodes, reheat_on_new_links, move_nodes))
+            .add_systems(FixedUpdate, step_layout);
+    }
+}
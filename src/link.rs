@@ -1,27 +1,80 @@
 use bevy::{prelude::*, utils::HashMap};
+use bevy_mod_picking::prelude::*;
 
 use crate::{
     camera::MainCamera,
-    node::{Node, NodeRegistry},
-    recipe::Recipes,
+    context_menu::ContextMenu,
+    ingredient::{IngredientIndex, Ingredients},
+    node::{setup_nodes, Node, NodeRegistry, NodeType},
+    recipe::{RecipeIndex, Recipes},
+    transport::Supply,
 };
 
+/// A directed edge in the graph, carrying ingredients from `from` to `to`.
+/// One end is always an ingredient node and the other a recipe node.
 #[derive(Component)]
 pub struct Link {
-    e1: Entity,
-    e2: Entity,
+    pub from: Entity,
+    pub to: Entity,
 }
 
 // This is probably a bad idea
 impl Default for Link {
     fn default() -> Self {
         Link {
-            e1: Entity::PLACEHOLDER,
-            e2: Entity::PLACEHOLDER,
+            from: Entity::PLACEHOLDER,
+            to: Entity::PLACEHOLDER,
         }
     }
 }
 
+/// Tracks how much of an ingredient is flowing along a link for a given recipe
+#[derive(Component, Debug)]
+pub struct LinkFlow {
+    pub recipe: RecipeIndex,
+    pub ingredient: IngredientIndex,
+    /// Items per second moving along the link
+    pub throughput: f64,
+    pub stalled: bool,
+    /// How far along the link the pulses have travelled, as a fraction of its length
+    phase: f32,
+}
+
+impl LinkFlow {
+    fn new(recipe: RecipeIndex, ingredient: IngredientIndex) -> Self {
+        LinkFlow {
+            recipe,
+            ingredient,
+            throughput: 0.0,
+            stalled: true,
+            phase: 0.0,
+        }
+    }
+
+    fn width(&self) -> f32 {
+        const MIN_WIDTH: f32 = 0.1;
+        const MAX_WIDTH: f32 = 0.4;
+        f32::min(
+            MIN_WIDTH + 0.05 * (self.throughput as f32).sqrt(),
+            MAX_WIDTH,
+        )
+    }
+
+    /// Speed of the pulses in world units per second
+    fn speed(&self) -> f32 {
+        const MIN_SPEED: f32 = 0.5;
+        const MAX_SPEED: f32 = 6.0;
+        f32::min(MIN_SPEED + (self.throughput as f32).sqrt(), MAX_SPEED)
+    }
+}
+
+/// A small marker travelling along a link to show which way ingredients flow
+#[derive(Component, Debug)]
+struct LinkPulse {
+    link: Entity,
+    offset: f32,
+}
+
 #[derive(Default, Bundle)]
 struct LinkBundle {
     pub mesh: Handle<Mesh>,
@@ -39,14 +92,12 @@ struct LinkRegistry {
 }
 
 impl LinkRegistry {
-    fn add_link(&mut self, e1: Entity, e2: Entity, link: Entity) -> Option<Entity> {
-        let k = (Entity::min(e1, e2), Entity::max(e1, e2));
-        self.map.insert(k, link)
+    fn add_link(&mut self, from: Entity, to: Entity, link: Entity) -> Option<Entity> {
+        self.map.insert((from, to), link)
     }
 
-    fn contains(&self, e1: Entity, e2: Entity) -> bool {
-        let k = (Entity::min(e1, e2), Entity::max(e1, e2));
-        self.map.contains_key(&k)
+    fn contains(&self, from: Entity, to: Entity) -> bool {
+        self.map.contains_key(&(from, to))
     }
 }
 
@@ -54,6 +105,9 @@ impl LinkRegistry {
 struct LinkVisuals {
     mesh: Handle<Mesh>,
     material: Handle<StandardMaterial>,
+    stalled_material: Handle<StandardMaterial>,
+    pulse_mesh: Handle<Mesh>,
+    pulse_material: Handle<StandardMaterial>,
 }
 
 fn setup_link_visuals(
@@ -70,8 +124,29 @@ fn setup_link_visuals(
     );
 
     let material = materials.add(Color::BLUE.into());
+    let stalled_material = materials.add(Color::GRAY.into());
+
+    let pulse_mesh = meshes.add(
+        shape::UVSphere {
+            radius: 0.12,
+            ..Default::default()
+        }
+        .into(),
+    );
+    let pulse_material = materials.add(StandardMaterial {
+        base_color: Color::CYAN,
+        emissive: Color::CYAN,
+        unlit: true,
+        ..Default::default()
+    });
 
-    commands.insert_resource(LinkVisuals { mesh, material })
+    commands.insert_resource(LinkVisuals {
+        mesh,
+        material,
+        stalled_material,
+        pulse_mesh,
+        pulse_material,
+    })
 }
 
 fn create_links(
@@ -81,66 +156,180 @@ fn create_links(
     link_visuals: Res<LinkVisuals>,
     mut commands: Commands,
 ) {
-    for (_i, holder) in recipes.enumerate() {
+    // Number of pulses travelling along each link at any time
+    const PULSES_PER_LINK: usize = 3;
+
+    let mut spawn_link = |from: Entity, to: Entity, flow: LinkFlow| {
+        if link_registry.contains(from, to) {
+            return;
+        }
+
+        let link = commands
+            .spawn((
+                LinkBundle {
+                    link: Link { from, to },
+                    mesh: link_visuals.mesh.clone(),
+                    material: link_visuals.material.clone(),
+                    ..Default::default()
+                },
+                flow,
+                // Pickable so hovering shows what flows along the link
+                PickableBundle::default(),
+                RaycastPickTarget::default(),
+                On::<Pointer<Click>>::run(handle_link_click),
+            ))
+            .id();
+
+        for k in 0..PULSES_PER_LINK {
+            commands.spawn((
+                PbrBundle {
+                    mesh: link_visuals.pulse_mesh.clone(),
+                    material: link_visuals.pulse_material.clone(),
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                },
+                LinkPulse {
+                    link,
+                    offset: k as f32 / PULSES_PER_LINK as f32,
+                },
+            ));
+        }
+
+        link_registry.add_link(from, to, link);
+    };
+
+    for (i, holder) in recipes.enumerate() {
         let recipe = &holder.recipe;
-        for (t1, _) in recipe.input.iter() {
-            for (t2, _) in recipe.output.iter() {
-                let Some(e1) = nodes.get(t1) else {
-                    continue;
-                };
-
-                let Some(e2) = nodes.get(t2) else {
-                    continue;
-                };
-
-                if link_registry.contains(*e1, *e2) {
-                    continue;
-                }
-
-                let link = commands
-                    .spawn(LinkBundle {
-                        link: Link { e1: *e1, e2: *e2 },
-                        mesh: link_visuals.mesh.clone(),
-                        material: link_visuals.material.clone(),
-                        ..Default::default()
-                    })
-                    .id();
-
-                link_registry.add_link(*e1, *e2, link);
-            }
+        let Some(&recipe_e) = nodes.get(&NodeType::Recipe(i)) else {
+            warn!("No node registered for recipe {}", recipe.id);
+            continue;
+        };
+
+        for (ty, _) in recipe.input.iter() {
+            let Some(&ingredient_e) = nodes.get(&NodeType::Ingredient(*ty)) else {
+                continue;
+            };
+            spawn_link(ingredient_e, recipe_e, LinkFlow::new(i, *ty));
+        }
+
+        for (ty, _) in recipe.output.iter() {
+            let Some(&ingredient_e) = nodes.get(&NodeType::Ingredient(*ty)) else {
+                continue;
+            };
+            spawn_link(recipe_e, ingredient_e, LinkFlow::new(i, *ty));
+        }
+    }
+}
+
+/// Right click opens the link's context menu
+fn handle_link_click(listener: Listener<Pointer<Click>>, mut context_menu: ResMut<ContextMenu>) {
+    if listener.button == PointerButton::Secondary {
+        context_menu.open(listener.target, listener.pointer_location.position);
+    }
+}
+
+fn update_link_flow(
+    mut query: Query<(
+        &mut LinkFlow,
+        &mut Handle<StandardMaterial>,
+        &Link,
+        Option<&Supply>,
+    )>,
+    node_query: Query<&Transform, With<Node>>,
+    recipes: Res<Recipes>,
+    ingredients: Res<Ingredients>,
+    link_visuals: Res<LinkVisuals>,
+    time: Res<Time>,
+) {
+    for (mut flow, mut material, link, supply) in query.iter_mut() {
+        let holder = recipes.get_recipe_holder(&flow.recipe);
+        flow.throughput = match supply {
+            Some(_) => holder.consumption_rate(flow.ingredient),
+            None => holder.production_rate(flow.ingredient),
+        };
+        flow.stalled = holder.stalled(&ingredients);
+
+        let desired_material = if flow.stalled {
+            &link_visuals.stalled_material
+        } else {
+            &link_visuals.material
+        };
+        if *material != *desired_material {
+            *material = desired_material.clone();
+        }
+
+        let (Ok(a), Ok(b)) = (node_query.get(link.from), node_query.get(link.to)) else {
+            continue;
+        };
+        let length = a.translation.distance(b.translation);
+        if !flow.stalled && length > f32::EPSILON {
+            let advance = flow.speed() * time.delta_seconds() / length;
+            flow.phase = (flow.phase + advance).fract();
+        }
+    }
+}
+
+fn update_link_pulses(
+    mut pulse_query: Query<(&mut Transform, &mut Visibility, &LinkPulse)>,
+    link_query: Query<(&Link, &LinkFlow, &Visibility), Without<LinkPulse>>,
+    node_query: Query<&Transform, (With<Node>, Without<LinkPulse>)>,
+) {
+    for (mut transform, mut visibility, pulse) in pulse_query.iter_mut() {
+        let Ok((link, flow, link_visibility)) = link_query.get(pulse.link) else {
+            continue;
+        };
+        let (Ok(a), Ok(b)) = (node_query.get(link.from), node_query.get(link.to)) else {
+            continue;
+        };
+
+        if flow.stalled || *link_visibility == Visibility::Hidden {
+            *visibility = Visibility::Hidden;
+            continue;
         }
+
+        let t = (flow.phase + pulse.offset).fract();
+        transform.translation = a.translation.lerp(b.translation, t);
+        *visibility = Visibility::Visible;
     }
 }
 
 fn update_links(
-    mut link_query: Query<(&mut Transform, &mut Visibility, &Link), Without<MainCamera>>,
+    mut link_query: Query<(&mut Transform, &mut Visibility, &Link, &LinkFlow), Without<MainCamera>>,
     node_query: Query<(&Transform, &Node), (Without<Link>, Without<MainCamera>)>,
-    camera_query: Query<&Transform, With<MainCamera>>,
+    camera_query: Query<(&Transform, &Projection), With<MainCamera>>,
 ) {
-    let Ok(camera_transform) = camera_query.get_single() else {
+    let Ok((camera_transform, projection)) = camera_query.get_single() else {
         debug!("No main camera");
         return;
     };
 
-    for (mut link_transform, mut link_visibility, Link { e1, e2 }) in link_query.iter_mut() {
-        let Ok((transform_a, node_a)) = node_query.get(*e1) else {
-            debug!("No node 1");
+    for (mut link_transform, mut link_visibility, Link { from, to }, flow) in link_query.iter_mut()
+    {
+        let Ok((transform_a, node_a)) = node_query.get(*from) else {
+            debug!("No source node");
             continue;
         };
-        let Ok((transform_b, node_b)) = node_query.get(*e2) else {
-            debug!("No node 2");
+        let Ok((transform_b, node_b)) = node_query.get(*to) else {
+            debug!("No destination node");
             continue;
         };
         let a = transform_a.translation;
         let b = transform_b.translation;
         let c = camera_transform.translation;
-        let closest_point_on_line = a + (c - a).project_onto(b - a);
-        let dir_to_camera = closest_point_on_line - c;
+        // Orthographic cameras look along the same direction everywhere,
+        // so links have to face that direction rather than the camera's position
+        let dir_to_camera = match projection {
+            Projection::Orthographic(_) => camera_transform.forward(),
+            Projection::Perspective(_) => {
+                let closest_point_on_line = a + (c - a).project_onto(b - a);
+                closest_point_on_line - c
+            }
+        };
 
         link_transform.translation = (a + b) / 2.0;
         link_transform.look_to(dir_to_camera, b - a);
         link_transform.scale.y = (b - a).length();
-        link_transform.scale.x = 0.2;
+        link_transform.scale.x = flow.width();
 
         if !node_a.visible || !node_b.visible {
             *link_visibility = Visibility::Hidden;
@@ -157,8 +346,16 @@ impl Plugin for LinkPlugin {
         app.init_resource::<LinkRegistry>()
             .add_systems(
                 Startup,
-                (setup_link_visuals, apply_deferred, create_links).chain(),
+                (
+                    setup_link_visuals,
+                    apply_deferred,
+                    create_links.after(setup_nodes),
+                )
+                    .chain(),
             )
-            .add_systems(Update, update_links);
+            .add_systems(
+                Update,
+                (update_link_flow, update_links, update_link_pulses).chain(),
+            );
     }
 }
@@ -1,25 +1,54 @@
-use bevy::{math::vec3, prelude::*};
+use bevy::{
+    math::{vec2, vec3},
+    prelude::*,
+};
 
+use belt::BeltPlugin;
+use bookmark::BookmarkPlugin;
 use camera::CameraPlugin;
+use context_menu::ContextMenuPlugin;
 use floating_text::FloatingTextPlugin;
-use game_builder::GameBuilder;
+use game_builder::{GameBuilder, UnlockRequirement};
 use ingredient::IngredientPlugin;
+use layout::LayoutPlugin;
 use link::LinkPlugin;
 use node::NodePlugin;
+use palette::PalettePlugin;
 use picking::PickingPlugin;
 use recipe::RecipePlugin;
+use research::ResearchPlugin;
+use save::SavePlugin;
+use selection::SelectionPlugin;
+use site::SitePlugin;
+use tooltip::TooltipPlugin;
+use transport::TransportPlugin;
 use ui::UiPlugin;
+use unlock::UnlockPlugin;
+use upgrade::UpgradePlugin;
 
+mod belt;
+mod bookmark;
 mod camera;
+mod context_menu;
 mod floating_text;
 mod game_builder;
 mod ingredient;
+mod layout;
 mod link;
 mod node;
+mod palette;
 mod picking;
 mod quantity;
 mod recipe;
+mod research;
+mod save;
+mod selection;
+mod site;
+mod tooltip;
+mod transport;
 mod ui;
+mod unlock;
+mod upgrade;
 mod utils;
 
 fn main() {
@@ -32,9 +61,24 @@ fn main() {
             UiPlugin,
             NodePlugin,
             LinkPlugin,
+            LayoutPlugin,
             CameraPlugin,
             PickingPlugin,
             FloatingTextPlugin,
+            SavePlugin,
+            UpgradePlugin,
+            UnlockPlugin,
+            ResearchPlugin,
+            BookmarkPlugin,
+            PalettePlugin,
+        ))
+        .add_plugins((
+            TooltipPlugin,
+            ContextMenuPlugin,
+            SelectionPlugin,
+            TransportPlugin,
+            BeltPlugin,
+            SitePlugin,
         ))
         .add_systems(Startup, setup);
 
@@ -72,11 +116,76 @@ fn main() {
             [("ingr_steel_ingot", 10.0)],
             15.0,
             true,
+        )
+        .add_upgrade(
+            "upgr_steel_furnace",
+            "Steel Furnace",
+            [("ingr_iron_ingot", 100.0), ("ingr_coal", 100.0)],
+        )
+        .add_site(
+            "site_home",
+            "Home",
+            Vec2::ZERO,
+            [
+                "reci_mine_iron_ore",
+                "reci_manual_iron_ore",
+                "reci_mine_coal",
+                "reci_smelt_iron",
+                "reci_smelt_steel",
+            ],
+        )
+        .add_site(
+            "site_mine",
+            "Mine",
+            vec2(40.0, 0.0),
+            ["reci_mine_iron_ore", "reci_mine_coal"],
+        )
+        .add_shipping_route(
+            "site_mine",
+            "site_home",
+            "ingr_iron_ore",
+            50.0,
+            20.0,
+            [("ingr_coal", 5.0)],
+        )
+        .add_shipping_route(
+            "site_mine",
+            "site_home",
+            "ingr_coal",
+            100.0,
+            20.0,
+            [("ingr_coal", 5.0)],
+        )
+        .set_cancel_refund(0.75)
+        .add_belt_tier::<&str>("Basic belt", 4.0, [])
+        .add_belt_tier("Fast belt", 12.0, [("ingr_iron_ingot", 20.0)])
+        .add_belt_tier("Express belt", 40.0, [("ingr_steel_ingot", 10.0)])
+        .add_research(
+            "rsch_metallurgy",
+            "Metallurgy",
+            [("ingr_iron_ingot", 1.0)],
+            30.0,
+            [],
+            ["upgr_steel_furnace"],
+        )
+        .add_unlock(
+            "reci_smelt_iron",
+            UnlockRequirement::StockAtLeast("ingr_iron_ore".into(), 20.0),
+        )
+        .add_unlock(
+            "ingr_iron_ingot",
+            UnlockRequirement::Produced("ingr_iron_ingot".into()),
+        )
+        .add_unlock(
+            "reci_smelt_steel",
+            UnlockRequirement::Upgrade("upgr_steel_furnace".into()),
+        )
+        .add_unlock(
+            "ingr_steel_ingot",
+            UnlockRequirement::Produced("ingr_steel_ingot".into()),
         );
 
-    let (ingredients, recipes) = game_builder.build();
-
-    app.insert_resource(ingredients).insert_resource(recipes);
+    game_builder.build(&mut app);
 
     app.run();
 }
@@ -5,24 +5,99 @@ use bevy::{prelude::*, utils::HashMap};
 use bevy_mod_picking::prelude::*;
 
 use crate::{
+    camera::MainCamera,
+    context_menu::ContextMenu,
     ingredient::{IngredientIndex, Ingredients},
-    ui::SelectedNode,
+    layout::LayoutNode,
+    recipe::{RecipeIndex, Recipes},
+    save::SaveData,
+    selection::Selection,
+    site::{SiteIndex, Sites},
 };
 
+/// What a node in the graph stands for
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum NodeType {
+    Ingredient(IngredientIndex),
+    Recipe(RecipeIndex),
+}
+
+impl NodeType {
+    /// Finds the node standing for the ingredient or recipe with this id
+    pub fn from_id(id: &str, ingredients: &Ingredients, recipes: &Recipes) -> Option<NodeType> {
+        if let Some((i, _)) = ingredients.iter().find(|(_, ingr)| ingr.id == id) {
+            return Some(NodeType::Ingredient(i));
+        }
+        recipes
+            .enumerate()
+            .find(|(_, holder)| holder.recipe.id == id)
+            .map(|(i, _)| NodeType::Recipe(i))
+    }
+
+    /// The site the ingredient or recipe this node stands for belongs to
+    pub fn site(&self, ingredients: &Ingredients, recipes: &Recipes) -> SiteIndex {
+        match self {
+            NodeType::Ingredient(i) => ingredients.get(*i).site,
+            NodeType::Recipe(i) => recipes.get_recipe(i).site,
+        }
+    }
+
+    /// The id of the ingredient or recipe this node stands for
+    pub fn id<'a>(&self, ingredients: &'a Ingredients, recipes: &'a Recipes) -> &'a str {
+        match self {
+            NodeType::Ingredient(i) => &ingredients.get(*i).id,
+            NodeType::Recipe(i) => &recipes.get_recipe(i).id,
+        }
+    }
+}
+
+impl From<IngredientIndex> for NodeType {
+    fn from(value: IngredientIndex) -> Self {
+        NodeType::Ingredient(value)
+    }
+}
+
+impl From<RecipeIndex> for NodeType {
+    fn from(value: RecipeIndex) -> Self {
+        NodeType::Recipe(value)
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct Node {
-    pub ty: IngredientIndex,
+    pub ty: NodeType,
     pub visible: bool,
 }
 
 #[derive(Component, Debug)]
 struct NodeScale {
     target_scale: f32,
+    hovered: bool,
 }
 
 impl Default for NodeScale {
     fn default() -> Self {
-        NodeScale { target_scale: 1.0 }
+        NodeScale {
+            target_scale: 1.0,
+            hovered: false,
+        }
+    }
+}
+
+/// Hovered and selected nodes are drawn a bit larger
+fn highlight_nodes(mut query: Query<(&Node, &mut NodeScale)>, selection: Res<Selection>) {
+    const HOVER_SCALE: f32 = 0.1;
+    const SELECTED_SCALE: f32 = 0.25;
+
+    for (node, mut node_scale) in query.iter_mut() {
+        let mut target_scale = 1.0;
+        if node_scale.hovered {
+            target_scale += HOVER_SCALE;
+        }
+        if selection.contains(node.ty) {
+            target_scale += SELECTED_SCALE;
+        }
+        node_scale.target_scale = target_scale;
     }
 }
 
@@ -37,14 +112,14 @@ fn scale_nodes(mut query: Query<(&mut Transform, &NodeScale)>, time: Res<Time>)
     }
 }
 
-/// Stores a mapping from `IngredientType` to the `Entity` of the corresponding node
+/// Stores a mapping from `NodeType` to the `Entity` of the corresponding node
 #[derive(Resource, Default)]
 pub struct NodeRegistry {
-    map: HashMap<IngredientIndex, Entity>,
+    map: HashMap<NodeType, Entity>,
 }
 
 impl core::ops::Deref for NodeRegistry {
-    type Target = HashMap<IngredientIndex, Entity>;
+    type Target = HashMap<NodeType, Entity>;
     fn deref(&self) -> &Self::Target {
         &self.map
     }
@@ -65,6 +140,8 @@ fn add_pointer_event_listeners(mut commands: Commands, query: Query<Entity, Adde
                 On::<Pointer<Over>>::run(handle_pointer_over),
                 On::<Pointer<Out>>::run(handle_pointer_out),
                 On::<Pointer<Click>>::run(handle_pointer_click),
+                On::<Pointer<Drag>>::run(handle_drag),
+                On::<Pointer<DragEnd>>::run(handle_drag_end),
             ));
     }
 }
@@ -74,23 +151,171 @@ fn handle_pointer_over(
     mut query: Query<(&Node, &mut NodeScale)>,
 ) {
     if let Ok((_, mut node_scale)) = query.get_mut(listener.target) {
-        node_scale.target_scale = 1.1;
+        node_scale.hovered = true;
     }
 }
 
 fn handle_pointer_out(listener: Listener<Pointer<Out>>, mut query: Query<(&Node, &mut NodeScale)>) {
     if let Ok((_, mut node_scale)) = query.get_mut(listener.target) {
-        node_scale.target_scale = 1.0;
+        node_scale.hovered = false;
     }
 }
 
+/// Left click selects the node, or adds it to the selection while holding shift.
+/// Right click opens its context menu.
 fn handle_pointer_click(
     listener: Listener<Pointer<Click>>,
     query: Query<&Node>,
-    mut selected_node: ResMut<SelectedNode>,
+    keys: Res<Input<KeyCode>>,
+    mut selection: ResMut<Selection>,
+    mut context_menu: ResMut<ContextMenu>,
+) {
+    let Ok(node) = query.get(listener.target) else {
+        return;
+    };
+
+    match listener.button {
+        PointerButton::Primary => {
+            if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+                selection.toggle(node.ty);
+            } else {
+                selection.select(node.ty);
+            }
+        }
+        PointerButton::Secondary => {
+            context_menu.open(listener.target, listener.pointer_location.position)
+        }
+        PointerButton::Middle => {}
+    }
+}
+
+/// Shows nodes once what they stand for is unlocked, and hides them otherwise
+/// or when the player hid them
+fn update_node_visibility(
+    mut query: Query<(&mut Node, &mut Visibility, &mut Transform)>,
+    ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    save_data: Res<SaveData>,
 ) {
-    if let Ok(node) = query.get(listener.target) {
-        selected_node.selected = Some(node.ty);
+    // Revealed nodes grow from this scale up to their normal size
+    const REVEAL_SCALE: f32 = 0.01;
+
+    for (mut node, mut visibility, mut transform) in query.iter_mut() {
+        let unlocked = match node.ty {
+            NodeType::Ingredient(i) => ingredients.get(i).unlocked,
+            NodeType::Recipe(i) => recipes.get_recipe_holder(&i).unlocked,
+        };
+        let shown = unlocked
+            && !save_data
+                .hidden_nodes
+                .contains(node.ty.id(&ingredients, &recipes));
+
+        if shown == node.visible {
+            continue;
+        }
+
+        node.visible = shown;
+        if shown {
+            *visibility = Visibility::Visible;
+            transform.scale = Vec3::splat(REVEAL_SCALE);
+        } else {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+/// Moves the dragged node to the point on the ground plane under the pointer.
+/// Dragging a selected node moves the rest of the selection along with it.
+fn handle_drag(
+    listener: Listener<Pointer<Drag>>,
+    mut query: Query<(Entity, &Node, &mut Transform, &mut LayoutNode)>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    selection: Res<Selection>,
+) {
+    if listener.button != PointerButton::Primary {
+        return;
+    }
+
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let Ok((_, dragged_node, dragged_transform, _)) = query.get(listener.target) else {
+        return;
+    };
+    let Some(ray) = camera.viewport_to_world(camera_transform, listener.pointer_location.position)
+    else {
+        return;
+    };
+    let plane_origin = Vec3::new(0.0, dragged_transform.translation.y, 0.0);
+    let Some(distance) = ray.intersect_plane(plane_origin, Vec3::Y) else {
+        return;
+    };
+
+    let point = ray.get_point(distance);
+    let delta = Vec2::new(
+        point.x - dragged_transform.translation.x,
+        point.z - dragged_transform.translation.z,
+    );
+    let drag_selection = selection.contains(dragged_node.ty);
+
+    for (e, node, mut transform, mut layout_node) in query.iter_mut() {
+        if e != listener.target && !(drag_selection && selection.contains(node.ty)) {
+            continue;
+        }
+        transform.translation.x += delta.x;
+        transform.translation.z += delta.y;
+        layout_node.position = Vec2::new(transform.translation.x, transform.translation.z);
+        layout_node.pinned = true;
+    }
+}
+
+fn handle_drag_end(
+    listener: Listener<Pointer<DragEnd>>,
+    query: Query<(Entity, &Node, &LayoutNode)>,
+    ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    selection: Res<Selection>,
+    mut save_data: ResMut<SaveData>,
+) {
+    if listener.button != PointerButton::Primary {
+        return;
+    }
+
+    let Ok((_, dragged_node, _)) = query.get(listener.target) else {
+        return;
+    };
+    let drag_selection = selection.contains(dragged_node.ty);
+
+    for (e, node, layout_node) in query.iter() {
+        if e != listener.target && !(drag_selection && selection.contains(node.ty)) {
+            continue;
+        }
+        let id = node.ty.id(&ingredients, &recipes).to_string();
+        save_data
+            .node_positions
+            .insert(id, layout_node.position.to_array());
+    }
+}
+
+/// Puts nodes the player has placed in a previous session back where they were
+fn restore_node_positions(
+    mut query: Query<(&Node, &mut LayoutNode, &mut Transform), Added<LayoutNode>>,
+    ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    save_data: Res<SaveData>,
+) {
+    for (node, mut layout_node, mut transform) in query.iter_mut() {
+        let Some(&[x, z]) = save_data
+            .node_positions
+            .get(node.ty.id(&ingredients, &recipes))
+        else {
+            continue;
+        };
+
+        layout_node.position = Vec2::new(x, z);
+        layout_node.pinned = true;
+        transform.translation.x = x;
+        transform.translation.z = z;
     }
 }
 
@@ -100,17 +325,28 @@ impl Plugin for NodePlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<NodeRegistry>()
             .add_systems(Startup, setup_nodes)
-            .add_systems(Update, (add_pointer_event_listeners, scale_nodes));
+            .add_systems(
+                Update,
+                (
+                    add_pointer_event_listeners,
+                    update_node_visibility.before(scale_nodes),
+                    highlight_nodes.before(scale_nodes),
+                    scale_nodes,
+                    restore_node_positions,
+                ),
+            );
     }
 }
 
 // TODO: Temporary
-fn setup_nodes(
+pub(crate) fn setup_nodes(
     mut commands: Commands,
     mut registry: ResMut<NodeRegistry>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    sites: Res<Sites>,
 ) {
     let mesh = meshes.add(
         shape::Icosphere {
@@ -122,29 +358,106 @@ fn setup_nodes(
         .unwrap(),
     );
 
-    for (ty, ingr) in ingredients.iter() {
-        let t = 2.0 * PI * (ty.ix() as f32 / ingredients.len() as f32);
+    // Every site gets its own rings, around its origin
+    let ingredient_rings = site_rings(ingredients.iter().map(|(_, ingr)| ingr.site), 0.0);
+    for ((ty, ingr), (site, t)) in ingredients.iter().zip(ingredient_rings) {
+        let t = 2.0 * PI * t;
+        let origin = sites.origin(site);
         let e = commands
             .spawn((
                 PbrBundle {
                     mesh: mesh.clone(),
                     material: materials.add(ingr.color.into()),
-                    transform: Transform::from_xyz(2.0 * f32::cos(t), 0.5, 2.0 * f32::sin(t)),
+                    transform: Transform::from_xyz(
+                        origin.x + 2.0 * f32::cos(t),
+                        0.5,
+                        origin.y + 2.0 * f32::sin(t),
+                    ),
                     ..Default::default()
                 },
-                Node { ty, visible: true },
+                Node {
+                    ty: ty.into(),
+                    visible: true,
+                },
                 NodeScale::default(),
             ))
             .id();
 
-        match registry.get_mut(&ty) {
-            None => {
-                registry.insert(ty, e);
-            }
-            Some(old_e) => {
-                *old_e = e;
-                warn!("Overwriting association in NodeRegistry")
-            }
+        register_node(&mut registry, ty.into(), e);
+    }
+
+    let recipe_mesh = meshes.add(
+        shape::Icosphere {
+            radius: 0.5,
+            subdivisions: 1,
+            ..Default::default()
+        }
+        .try_into()
+        .unwrap(),
+    );
+    let recipe_material = materials.add(Color::WHITE.into());
+
+    // Recipes sit on an outer ring, offset by half a step so links from the inner ring fan out
+    let recipe_rings = site_rings(
+        recipes.enumerate().map(|(_, holder)| holder.recipe.site),
+        0.5,
+    );
+    for ((i, _), (site, t)) in recipes.enumerate().zip(recipe_rings) {
+        let t = 2.0 * PI * t;
+        let origin = sites.origin(site);
+        let e = commands
+            .spawn((
+                PbrBundle {
+                    mesh: recipe_mesh.clone(),
+                    material: recipe_material.clone(),
+                    transform: Transform::from_xyz(
+                        origin.x + 4.0 * f32::cos(t),
+                        0.5,
+                        origin.y + 4.0 * f32::sin(t),
+                    ),
+                    ..Default::default()
+                },
+                Node {
+                    ty: i.into(),
+                    visible: true,
+                },
+                NodeScale::default(),
+            ))
+            .id();
+
+        register_node(&mut registry, i.into(), e);
+    }
+}
+
+/// For things belonging to the given sites, in order, their site and how far around its
+/// ring they go, as a fraction of a full turn. `offset` is in steps between neighbours.
+fn site_rings(sites: impl Iterator<Item = SiteIndex>, offset: f32) -> Vec<(SiteIndex, f32)> {
+    let sites: Vec<SiteIndex> = sites.collect();
+    let mut counts: HashMap<SiteIndex, usize> = HashMap::new();
+    for site in sites.iter() {
+        *counts.entry(*site).or_default() += 1;
+    }
+
+    let mut placed: HashMap<SiteIndex, usize> = HashMap::new();
+    sites
+        .into_iter()
+        .map(|site| {
+            let n = placed.entry(site).or_default();
+            let t = (*n as f32 + offset) / counts[&site] as f32;
+            *n += 1;
+            (site, t)
+        })
+        .collect()
+}
+
+fn register_node(registry: &mut NodeRegistry, ty: NodeType, e: Entity) {
+    match registry.get_mut(&ty) {
+        None => {
+            registry.insert(ty, e);
+        }
+        Some(old_e) => {
+            *old_e = e;
+            warn!("Overwriting association in NodeRegistry")
         }
     }
 }
@@ -0,0 +1,286 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiSet};
+
+use crate::{
+    camera::SetTarget,
+    ingredient::Ingredients,
+    node::{NodeRegistry, NodeType},
+    recipe::{process_recipe_events, RecipeEvent, RecipeIndex, Recipes},
+    selection::Selection,
+    upgrade::{UpgradeEvent, UpgradeIndex, Upgrades},
+};
+
+/// The Ctrl+K palette for jumping to nodes and running actions by name
+#[derive(Resource, Debug, Default)]
+pub struct CommandPalette {
+    pub open: bool,
+    query: String,
+    /// Index of the highlighted entry among the current matches
+    selected: usize,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub enum PaletteAction {
+    /// Selects the node and moves the camera to it
+    Select(NodeType),
+    Craft(RecipeIndex),
+    ToggleAutomation(RecipeIndex),
+    Buy(UpgradeIndex),
+}
+
+struct PaletteEntry {
+    label: String,
+    detail: &'static str,
+    action: PaletteAction,
+    enabled: bool,
+}
+
+/// Scores how well `pattern` matches `text` as a case-insensitive subsequence, or `None` if
+/// it doesn't match at all. Consecutive characters and characters at the start of a word score
+/// higher, so "si" prefers "Steel Ingot" over "Silicon".
+fn fuzzy_score(pattern: &str, text: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut text_chars = text.chars().enumerate();
+    let mut previous_match = None;
+    let mut previous_char = None;
+
+    for p in pattern.chars().filter(|c| !c.is_whitespace()) {
+        let p = p.to_ascii_lowercase();
+        loop {
+            let (i, c) = text_chars.next()?;
+            let before = previous_char.replace(c);
+            if c.to_ascii_lowercase() != p {
+                continue;
+            }
+
+            score += 1;
+            if previous_match.is_some_and(|j| j + 1 == i) {
+                score += 3;
+            }
+            if !before.is_some_and(|b| b.is_alphanumeric()) {
+                score += 2;
+            }
+
+            previous_match = Some(i);
+            break;
+        }
+    }
+
+    Some(score)
+}
+
+fn palette_entries(
+    ingredients: &Ingredients,
+    recipes: &Recipes,
+    upgrades: &Upgrades,
+) -> Vec<PaletteEntry> {
+    let mut entries = vec![];
+
+    for (i, ingr) in ingredients.iter() {
+        if ingr.unlocked {
+            entries.push(PaletteEntry {
+                label: ingr.name.clone(),
+                detail: "Ingredient",
+                action: PaletteAction::Select(NodeType::Ingredient(i)),
+                enabled: true,
+            });
+        }
+    }
+
+    for (i, holder) in recipes.enumerate() {
+        if !holder.unlocked {
+            continue;
+        }
+        entries.push(PaletteEntry {
+            label: holder.recipe.id.clone(),
+            detail: "Recipe",
+            action: PaletteAction::Select(NodeType::Recipe(i)),
+            enabled: true,
+        });
+        entries.push(PaletteEntry {
+            label: format!("Craft {}", holder.recipe.id),
+            detail: "Action",
+            action: PaletteAction::Craft(i),
+            // Crafts are queued, so they can always be asked for
+            enabled: true,
+        });
+        if holder.recipe.automatic {
+            entries.push(PaletteEntry {
+                label: format!("Toggle automation of {}", holder.recipe.id),
+                detail: match holder.automation_enabled {
+                    true => "On",
+                    false => "Off",
+                },
+                action: PaletteAction::ToggleAutomation(i),
+                enabled: true,
+            });
+        }
+    }
+
+    for (i, upgrade) in upgrades.enumerate() {
+        if upgrade.unlocked && !upgrade.purchased {
+            entries.push(PaletteEntry {
+                label: format!("Buy {}", upgrade.name),
+                detail: "Upgrade",
+                action: PaletteAction::Buy(i),
+                enabled: upgrade.can_afford(ingredients),
+            });
+        }
+    }
+
+    entries
+}
+
+/// Ctrl+K opens and closes the palette, Escape closes it
+fn toggle_palette(keys: Res<Input<KeyCode>>, mut palette: ResMut<CommandPalette>) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if ctrl && keys.just_pressed(KeyCode::K) {
+        palette.open = !palette.open;
+    } else if palette.open && keys.just_pressed(KeyCode::Escape) {
+        palette.open = false;
+    }
+}
+
+fn draw_palette(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
+    mut palette: ResMut<CommandPalette>,
+    ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    upgrades: Res<Upgrades>,
+    mut writer: EventWriter<PaletteAction>,
+) {
+    // Number of matches shown at once
+    const MAX_RESULTS: usize = 10;
+
+    if !palette.open {
+        return;
+    }
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    let mut matches: Vec<(i32, PaletteEntry)> = palette_entries(&ingredients, &recipes, &upgrades)
+        .into_iter()
+        .filter_map(|entry| Some((fuzzy_score(&palette.query, &entry.label)?, entry)))
+        .collect();
+    matches.sort_by_key(|(score, entry)| (-score, entry.label.len()));
+    matches.truncate(MAX_RESULTS);
+
+    // Taken before the text field sees them, which would otherwise move its cursor
+    let (up, down, enter) = ctx.input_mut(|input| {
+        (
+            input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            input.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+        )
+    });
+    if up {
+        palette.selected = palette.selected.saturating_sub(1);
+    }
+    if down {
+        palette.selected += 1;
+    }
+    palette.selected = usize::min(palette.selected, matches.len().saturating_sub(1));
+
+    let mut chosen = None;
+    if enter {
+        chosen = matches
+            .get(palette.selected)
+            .filter(|(_, entry)| entry.enabled)
+            .map(|(_, entry)| entry.action);
+    }
+
+    egui::Window::new("Command palette")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 48.0])
+        .show(ctx, |ui| {
+            ui.set_width(360.0);
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut palette.query)
+                    .hint_text("Search ingredients, recipes and actions")
+                    .desired_width(f32::INFINITY),
+            );
+            response.request_focus();
+            if response.changed() {
+                palette.selected = 0;
+            }
+
+            ui.separator();
+            if matches.is_empty() {
+                ui.weak("No matches");
+            }
+            for (i, (_, entry)) in matches.iter().enumerate() {
+                ui.add_enabled_ui(entry.enabled, |ui| {
+                    ui.horizontal(|ui| {
+                        let clicked = ui
+                            .selectable_label(i == palette.selected, &entry.label)
+                            .clicked();
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.weak(entry.detail);
+                        });
+                        if clicked {
+                            chosen = Some(entry.action);
+                        }
+                    });
+                });
+            }
+        });
+
+    if let Some(action) = chosen {
+        writer.send(action);
+        palette.open = false;
+        palette.query.clear();
+        palette.selected = 0;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn process_palette_actions(
+    mut reader: EventReader<PaletteAction>,
+    mut selection: ResMut<Selection>,
+    node_registry: Res<NodeRegistry>,
+    mut recipes: ResMut<Recipes>,
+    mut target_writer: EventWriter<SetTarget>,
+    mut recipe_writer: EventWriter<RecipeEvent>,
+    mut upgrade_writer: EventWriter<UpgradeEvent>,
+) {
+    for action in reader.iter() {
+        match *action {
+            PaletteAction::Select(ty) => {
+                selection.select(ty);
+                match node_registry.get(&ty) {
+                    Some(e) => target_writer.send(SetTarget(*e)),
+                    None => warn!("No node exists for {:?}", ty),
+                }
+            }
+            PaletteAction::Craft(i) => recipe_writer.send(RecipeEvent::QueueRecipe(i, 1)),
+            PaletteAction::ToggleAutomation(i) => {
+                let holder = recipes.get_recipe_holder_mut(&i);
+                holder.automation_enabled = !holder.automation_enabled;
+            }
+            PaletteAction::Buy(i) => upgrade_writer.send(UpgradeEvent::Purchase(i)),
+        }
+    }
+}
+
+pub struct PalettePlugin;
+
+impl Plugin for PalettePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PaletteAction>()
+            .init_resource::<CommandPalette>()
+            .add_systems(
+                Update,
+                (
+                    toggle_palette,
+                    process_palette_actions.before(process_recipe_events),
+                ),
+            )
+            .add_systems(PostUpdate, draw_palette.after(EguiSet::InitContexts));
+    }
+}
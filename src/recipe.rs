@@ -1,10 +1,17 @@
-use bevy::prelude::*;
+use std::cmp::Reverse;
+
+use bevy::{prelude::*, utils::HashMap};
 
 use crate::{
     ingredient::{IngredientIndex, Ingredients},
     quantity::Quantity,
+    site::SiteIndex,
+    transport::Logistics,
 };
 
+/// How many cycles' worth of inputs a recipe's buffer holds
+pub const BUFFER_CYCLES: f64 = 2.0;
+
 #[derive(Debug)]
 pub struct Recipe {
     pub id: String,
@@ -12,13 +19,15 @@ pub struct Recipe {
     pub output: Vec<(IngredientIndex, Quantity)>,
     pub automatic: bool,
     pub delay: Quantity,
+    /// The site running this copy of the recipe, using that site's copies of the ingredients
+    pub site: SiteIndex,
 }
 
 impl Recipe {
     pub fn can_run_n_times(&self, ingredients: &Ingredients, n: u32) -> bool {
         for &(input_ingredient, amount) in &self.input {
             let ingredient = ingredients.get(input_ingredient);
-            if ingredient.current < amount.value() * n as f64 {
+            if ingredient.available() < amount.value() * n as f64 {
                 return false;
             }
         }
@@ -32,6 +41,30 @@ impl Recipe {
     pub fn can_run(&self, ingredients: &Ingredients) -> bool {
         self.can_run_n_times(ingredients, 1)
     }
+
+    /// Amount of `ty` consumed in one cycle
+    pub fn amount_consumed(&self, ty: IngredientIndex) -> f64 {
+        Self::amount_per_cycle(&self.input, ty)
+    }
+
+    /// Amount of `ty` produced in one cycle
+    pub fn amount_produced(&self, ty: IngredientIndex) -> f64 {
+        Self::amount_per_cycle(&self.output, ty)
+    }
+
+    fn amount_per_cycle(list: &[(IngredientIndex, Quantity)], ty: IngredientIndex) -> f64 {
+        list.iter()
+            .filter(|(i, _)| *i == ty)
+            .map(|(_, q)| q.value())
+            .sum()
+    }
+
+    /// Whether any of the outputs has reached the stock limit the player set for it
+    pub fn output_at_stock_limit(&self, ingredients: &Ingredients) -> bool {
+        self.output
+            .iter()
+            .any(|(ty, _)| ingredients.get(*ty).at_stock_limit())
+    }
 }
 
 #[derive(Debug)]
@@ -40,22 +73,230 @@ pub struct RecipeHolder {
     pub automation_enabled: bool,
     pub time: f64,
     pub started: bool,
+    /// Smoothed number of cycles completed per second
+    pub rate: f64,
+    pub unlocked: bool,
+    /// Shortest time a cycle can take without going over the capacity of the recipe's links
+    pub link_cycle_time: f64,
+    /// The recipe node's own stock of its inputs, in the order of `recipe.input`. Only used in
+    /// logistics mode, otherwise recipes take their inputs straight from the global stock
+    pub buffer: Option<Vec<f64>>,
+    /// Recipes with a higher priority get scarce inputs first. With proportional sharing it's
+    /// the recipe's weight instead.
+    pub priority: i32,
+    /// Per input, stock below which this recipe won't take any, in the order of `recipe.input`.
+    /// The ingredient's own keep threshold still applies, whichever of the two is higher wins.
+    pub input_floors: Vec<Option<f64>>,
+    /// Per output, stock at which automation stops starting the recipe, in the order of
+    /// `recipe.output`
+    pub output_targets: Vec<Option<f64>>,
+    /// Cycles the player asked for that haven't started yet. Any cycle starting counts
+    /// towards them, whether it was queued or not.
+    pub queued: u32,
+    /// When `tick_recipes` last let this recipe take its inputs, for round robin allocation
+    pub last_served: u64,
 }
 
 impl RecipeHolder {
     pub fn from_recipe(recipe: Recipe) -> Self {
+        let input_floors = vec![None; recipe.input.len()];
+        let output_targets = vec![None; recipe.output.len()];
         RecipeHolder {
             recipe,
             automation_enabled: true,
             time: 0.0,
             started: false,
+            rate: 0.0,
+            unlocked: true,
+            link_cycle_time: 0.0,
+            buffer: None,
+            priority: 0,
+            input_floors,
+            output_targets,
+            queued: 0,
+            last_served: 0,
         }
     }
+
+    /// Seconds one cycle takes, which is longer than the delay when the links can't keep up
+    pub fn cycle_time(&self) -> f64 {
+        f64::max(self.recipe.delay.value(), self.link_cycle_time)
+    }
+
+    /// Whether there are enough inputs for a cycle, in the buffer if the recipe has one
+    pub fn can_run(&self, ingredients: &Ingredients) -> bool {
+        match &self.buffer {
+            None => {
+                self.recipe.can_run(ingredients)
+                    && self
+                        .recipe
+                        .input
+                        .iter()
+                        .enumerate()
+                        .all(|(k, (_, amount))| self.available(k, ingredients) >= amount.value())
+            }
+            Some(buffer) => self
+                .recipe
+                .input
+                .iter()
+                .zip(buffer)
+                .all(|((_, amount), stored)| *stored >= amount.value()),
+        }
+    }
+
+    /// How much of the input at `k` this recipe may take from the stock, which is everything
+    /// above the higher of the ingredient's keep threshold and the recipe's own floor
+    pub fn available(&self, k: usize, ingredients: &Ingredients) -> f64 {
+        let ingredient = ingredients.get(self.recipe.input[k].0);
+        let floor = self.input_floors[k].unwrap_or(0.0);
+        f64::min(ingredient.available(), ingredient.current - floor)
+    }
+
+    /// Most of the input at `k` the buffer holds
+    pub fn buffer_capacity(&self, k: usize) -> f64 {
+        self.recipe.input[k].1.value() * BUFFER_CYCLES
+    }
+
+    /// Amount of `ty` waiting in the buffer
+    pub fn buffered(&self, ty: IngredientIndex) -> f64 {
+        let Some(buffer) = &self.buffer else {
+            return 0.0;
+        };
+        self.recipe
+            .input
+            .iter()
+            .zip(buffer)
+            .filter(|((i, _), _)| *i == ty)
+            .map(|(_, stored)| stored)
+            .sum()
+    }
+
+    /// Whether any output has reached the recipe's target for it, the stock limit of its
+    /// ingredient, or is full, so running the recipe would only waste inputs
+    pub fn output_target_reached(&self, ingredients: &Ingredients) -> bool {
+        self.recipe.output_at_stock_limit(ingredients)
+            || self
+                .recipe
+                .output
+                .iter()
+                .zip(self.output_targets.iter())
+                .any(|((ty, _), target)| {
+                    let ingredient = ingredients.get(*ty);
+                    let full = ingredient
+                        .capacity
+                        .is_some_and(|cap| ingredient.current >= cap.value());
+                    full || target.is_some_and(|target| ingredient.current >= target)
+                })
+    }
+
+    /// Whether `tick_recipes` should start this recipe, assuming it isn't running already
+    pub fn should_start_automatically(&self, ingredients: &Ingredients) -> bool {
+        self.unlocked
+            && self.recipe.automatic
+            && self.automation_enabled
+            && self.can_run(ingredients)
+            && !self.output_target_reached(ingredients)
+    }
+
+    /// Whether `tick_recipes` should start a queued cycle of this recipe, assuming it isn't
+    /// running already. Unlike automation, queued cycles ignore output targets.
+    pub fn should_start_from_queue(&self, ingredients: &Ingredients) -> bool {
+        self.unlocked && self.queued > 0 && self.can_run(ingredients)
+    }
+
+    /// Seconds until the queue is done, assuming inputs are there whenever a cycle is due
+    pub fn queue_eta(&self) -> f64 {
+        let current = match self.started {
+            true => f64::max(0.0, self.cycle_time() - self.time),
+            false => 0.0,
+        };
+        current + self.queued as f64 * self.cycle_time()
+    }
+
+    /// A recipe is stalled when it isn't running and won't be started automatically or from
+    /// the queue either
+    pub fn stalled(&self, ingredients: &Ingredients) -> bool {
+        !self.started
+            && !self.should_start_automatically(ingredients)
+            && !self.should_start_from_queue(ingredients)
+    }
+
+    /// Amount of `ty` consumed per second by this recipe
+    pub fn consumption_rate(&self, ty: IngredientIndex) -> f64 {
+        self.recipe.amount_consumed(ty) * self.rate
+    }
+
+    /// Amount of `ty` produced per second by this recipe
+    pub fn production_rate(&self, ty: IngredientIndex) -> f64 {
+        self.recipe.amount_produced(ty) * self.rate
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct RecipeIndex(usize);
 
+/// How inputs are shared out when several recipes want to start but there isn't enough for all
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum AllocationStrategy {
+    /// Higher priority recipes always go first, ties go to the recipe registered first
+    #[default]
+    StrictPriority,
+    /// Higher priority recipes go first, and recipes with the same priority take turns
+    RoundRobin,
+    /// Every recipe gets a share of the inputs in proportion to its priority
+    Proportional,
+}
+
+impl AllocationStrategy {
+    pub const ALL: [AllocationStrategy; 3] = [
+        AllocationStrategy::StrictPriority,
+        AllocationStrategy::RoundRobin,
+        AllocationStrategy::Proportional,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AllocationStrategy::StrictPriority => "Strict priority",
+            AllocationStrategy::RoundRobin => "Round robin",
+            AllocationStrategy::Proportional => "Proportional share",
+        }
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct Allocation {
+    pub strategy: AllocationStrategy,
+    /// Counts calls of `tick_recipes`, to tell when recipes last got their inputs
+    tick: u64,
+}
+
+impl Allocation {
+    /// Sorts `candidates` into the order they get to take their inputs, both when starting
+    /// from the stock and when filling their buffers in logistics mode
+    pub fn order(&self, candidates: &mut [RecipeIndex], recipes: &Recipes) {
+        let holder = |i: &RecipeIndex| recipes.get_recipe_holder(i);
+        match self.strategy {
+            AllocationStrategy::StrictPriority => {
+                candidates.sort_by_key(|i| (Reverse(holder(i).priority), *i));
+            }
+            AllocationStrategy::RoundRobin => {
+                // Within a priority, whoever has waited longest for their inputs goes first
+                candidates
+                    .sort_by_key(|i| (Reverse(holder(i).priority), holder(i).last_served, *i));
+            }
+            AllocationStrategy::Proportional => {
+                // The recipe furthest below its share goes first. Weights start at 1, so
+                // recipes with a priority of 0 or less still get some
+                let share = |i: &RecipeIndex| {
+                    let weight = i32::max(holder(i).priority, 0) as f64 + 1.0;
+                    holder(i).rate / weight
+                };
+                candidates.sort_by(|a, b| f64::total_cmp(&share(a), &share(b)).then(a.cmp(b)));
+            }
+        }
+    }
+}
+
 #[derive(Resource, Default)]
 pub struct Recipes {
     recipes: Vec<RecipeHolder>,
@@ -76,6 +317,19 @@ impl Recipes {
             .map(|(i, r)| (RecipeIndex(i), r))
     }
 
+    /// Amount of `ty` produced per second by all recipes, minus the amount consumed
+    pub fn net_rate(&self, ty: IngredientIndex) -> f64 {
+        self.recipes
+            .iter()
+            .map(|holder| holder.production_rate(ty) - holder.consumption_rate(ty))
+            .sum()
+    }
+
+    /// Amount of `ty` waiting in the buffers of all recipes
+    pub fn buffered(&self, ty: IngredientIndex) -> f64 {
+        self.recipes.iter().map(|holder| holder.buffered(ty)).sum()
+    }
+
     pub fn get_recipe(&self, index: &RecipeIndex) -> &Recipe {
         &self.get_recipe_holder(index).recipe
     }
@@ -109,38 +363,121 @@ impl Recipes {
     }
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Event, Debug)]
 pub enum RecipeEvent {
     StartRecipe(RecipeIndex),
     FinishRecipe(RecipeIndex),
+    /// Stops a running recipe and gives back part of its inputs, see `CancelRefund`
+    CancelRecipe(RecipeIndex),
+    /// Asks for the recipe to run this many more times, once inputs allow
+    QueueRecipe(RecipeIndex, u32),
+    ClearQueue(RecipeIndex),
+}
+
+/// Sent by `process_recipe_events` for every `FinishRecipe` it accepts, so the outputs are
+/// handed out once. A cycle cancelled in the same frame as it finishes doesn't get one.
+#[derive(Event, Debug)]
+pub struct RecipeCompleted(pub RecipeIndex);
+
+/// Fraction of the inputs given back when a running recipe is cancelled
+#[derive(Resource, Debug)]
+pub struct CancelRefund {
+    pub fraction: f64,
+}
+
+impl CancelRefund {
+    /// Label of buttons cancelling a recipe, saying how much is refunded
+    pub fn button_label(&self) -> String {
+        format!("Cancel (refund {:.0}%)", self.fraction * 100.0)
+    }
+}
+
+impl Default for CancelRefund {
+    fn default() -> Self {
+        CancelRefund { fraction: 1.0 }
+    }
 }
 
 fn tick_recipes(
     mut recipes: ResMut<Recipes>,
     ingredients: Res<Ingredients>,
+    mut allocation: ResMut<Allocation>,
     mut writer: EventWriter<RecipeEvent>,
     time: Res<Time>,
 ) {
+    let mut candidates: Vec<RecipeIndex> = vec![];
     for (i, recipe_holder) in recipes.recipes.iter_mut().enumerate() {
         if recipe_holder.started {
             recipe_holder.time += time.delta_seconds_f64();
 
-            if recipe_holder.time >= recipe_holder.recipe.delay.value() {
+            if recipe_holder.time >= recipe_holder.cycle_time() {
                 writer.send(RecipeEvent::FinishRecipe(RecipeIndex(i)))
             }
-        } else if recipe_holder.recipe.automatic
-            && recipe_holder.automation_enabled
-            && recipe_holder.recipe.can_run(&ingredients)
+        } else if recipe_holder.should_start_automatically(&ingredients)
+            || recipe_holder.should_start_from_queue(&ingredients)
         {
-            writer.send(RecipeEvent::StartRecipe(RecipeIndex(i)))
+            candidates.push(RecipeIndex(i));
+        }
+    }
+
+    // Every candidate could start on its own, but together they may need more than the stock
+    // holds. Inputs are set aside in the order the strategy decides, so nobody starts without
+    // getting their share.
+    allocation.tick += 1;
+    allocation.order(&mut candidates, &recipes);
+    let mut reserved: HashMap<IngredientIndex, f64> = HashMap::new();
+    for i in candidates {
+        let recipe_holder = recipes.get_recipe_holder(&i);
+        // Recipes with their own buffer don't compete for the global stock here, `fill_buffers`
+        // already shared it out in the same order
+        if recipe_holder.buffer.is_none() {
+            let enough = recipe_holder
+                .recipe
+                .input
+                .iter()
+                .enumerate()
+                .all(|(k, (ty, amount))| {
+                    let taken = reserved.get(ty).copied().unwrap_or(0.0);
+                    recipe_holder.available(k, &ingredients) - taken >= amount.value()
+                });
+            if !enough {
+                continue;
+            }
+            for (ty, amount) in &recipe_holder.recipe.input {
+                *reserved.entry(*ty).or_insert(0.0) += amount.value();
+            }
         }
+        recipes.get_recipe_holder_mut(&i).last_served = allocation.tick;
+        writer.send(RecipeEvent::StartRecipe(i))
     }
 }
 
-fn process_recipe_events(
+fn track_recipe_rates(mut recipes: ResMut<Recipes>, time: Res<Time>) {
+    // Time constant of the smoothing, in seconds
+    const RATE_SMOOTHING: f64 = 1.0;
+
+    let dt = time.delta_seconds_f64();
+    let t = 1.0 - f64::exp(-dt / RATE_SMOOTHING);
+
+    for recipe_holder in recipes.recipes.iter_mut() {
+        let target_rate = if recipe_holder.started {
+            1.0 / f64::max(recipe_holder.cycle_time(), dt)
+        } else {
+            0.0
+        };
+
+        recipe_holder.rate += (target_rate - recipe_holder.rate) * t;
+    }
+}
+
+pub(crate) fn process_recipe_events(
     mut recipes: ResMut<Recipes>,
     mut ingredients: ResMut<Ingredients>,
     mut reader: EventReader<RecipeEvent>,
+    logistics: Res<Logistics>,
+    refund: Res<CancelRefund>,
+    mut completed_writer: EventWriter<RecipeCompleted>,
 ) {
     for event in reader.into_iter() {
         match event {
@@ -150,27 +487,102 @@ fn process_recipe_events(
 
                 let recipe_holder = recipes.get_recipe_holder_mut(i);
 
-                // Deduct input ingredients
-                for (ty, amount) in &recipe_holder.recipe.input {
-                    let ingredient = ingredients.get_mut(*ty);
-                    ingredient.spend_ingredient(amount.value());
+                // `tick_recipes` only starts recipes that aren't running, but a running cycle
+                // must never take its inputs a second time
+                if recipe_holder.started {
+                    continue;
+                }
+
+                // Deduct input ingredients, from the recipe's own buffer if it has one
+                match &mut recipe_holder.buffer {
+                    Some(buffer) => {
+                        for ((_, amount), stored) in recipe_holder.recipe.input.iter().zip(buffer) {
+                            *stored = f64::max(0.0, *stored - amount.value());
+                        }
+                    }
+                    None => {
+                        for (ty, amount) in &recipe_holder.recipe.input {
+                            let ingredient = ingredients.get_mut(*ty);
+                            ingredient.spend_ingredient(amount.value());
+                        }
+                    }
                 }
 
                 // Flag the recipe so it starts ticking
                 recipe_holder.started = true;
+                recipe_holder.queued = recipe_holder.queued.saturating_sub(1);
             }
             RecipeEvent::FinishRecipe(i) => {
                 let recipe_holder = recipes.get_recipe_holder_mut(i);
 
-                // Add output ingredients
-                for (ty, amount) in &recipe_holder.recipe.output {
-                    let ingredient = ingredients.get_mut(*ty);
-                    ingredient.add_ingredient(amount.value());
+                // Cancelled earlier in the same frame, and the inputs were refunded already
+                if !recipe_holder.started {
+                    continue;
+                }
+
+                // Add output ingredients, unless they have to travel along the links first
+                if !logistics.enabled {
+                    for (ty, amount) in &recipe_holder.recipe.output {
+                        let ingredient = ingredients.get_mut(*ty);
+                        ingredient.add_ingredient(amount.value());
+                        ingredient.total_produced += amount.value();
+                    }
                 }
 
                 // Reset the recipe
                 recipe_holder.started = false;
                 recipe_holder.time = 0.0;
+                completed_writer.send(RecipeCompleted(*i));
+            }
+            RecipeEvent::CancelRecipe(i) => {
+                let recipe_holder = recipes.get_recipe_holder_mut(i);
+
+                // Finished or cancelled earlier in the same frame
+                if !recipe_holder.started {
+                    continue;
+                }
+
+                // Refund inputs to where they were taken from. Whatever doesn't fit in the
+                // buffer goes to the stock, and whatever doesn't fit there is lost.
+                for k in 0..recipe_holder.recipe.input.len() {
+                    let (ty, amount) = recipe_holder.recipe.input[k];
+                    let mut refund = amount.value() * refund.fraction;
+                    let buffer_capacity = recipe_holder.buffer_capacity(k);
+                    if let Some(buffer) = &mut recipe_holder.buffer {
+                        let fits = f64::min(refund, f64::max(0.0, buffer_capacity - buffer[k]));
+                        buffer[k] += fits;
+                        refund -= fits;
+                    }
+                    if refund <= 0.0 {
+                        continue;
+                    }
+
+                    let ingredient = ingredients.get_mut(ty);
+                    let lost = ingredient.add_ingredient(refund);
+                    if lost > 0.0 {
+                        info!(
+                            "Cancelling {} refunded more {} than fits, {} was lost",
+                            recipe_holder.recipe.id, ingredient.name, lost
+                        );
+                    }
+                }
+
+                recipe_holder.started = false;
+                recipe_holder.time = 0.0;
+            }
+            RecipeEvent::QueueRecipe(i, n) => {
+                let recipe_holder = recipes.get_recipe_holder_mut(i);
+                if !recipe_holder.unlocked {
+                    warn!(
+                        "Tried to queue {}, which isn't unlocked yet",
+                        recipe_holder.recipe.id
+                    );
+                    continue;
+                }
+                recipe_holder.queued = recipe_holder.queued.saturating_add(*n);
+            }
+            RecipeEvent::ClearQueue(i) => {
+                recipes.get_recipe_holder_mut(i).queued = 0;
             }
         }
     }
@@ -181,8 +593,111 @@ pub struct RecipePlugin;
 impl Plugin for RecipePlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<RecipeEvent>()
+            .add_event::<RecipeCompleted>()
             .init_resource::<Recipes>()
+            .init_resource::<Allocation>()
+            .init_resource::<CancelRefund>()
             .add_systems(Update, tick_recipes)
-            .add_systems(Update, process_recipe_events.after(tick_recipes));
+            .add_systems(Update, process_recipe_events.after(tick_recipes))
+            .add_systems(Update, track_recipe_rates.after(process_recipe_events));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipes(priorities: &[i32]) -> Recipes {
+        let mut recipes = Recipes::default();
+        for (k, priority) in priorities.iter().enumerate() {
+            let i = recipes.add_recipe(Recipe {
+                id: format!("reci_{k}"),
+                input: vec![],
+                output: vec![],
+                automatic: true,
+                delay: Quantity::new(1.0),
+                site: SiteIndex::default(),
+            });
+            recipes.get_recipe_holder_mut(&i).priority = *priority;
+        }
+        recipes
+    }
+
+    fn ordered(allocation: &Allocation, recipes: &Recipes) -> Vec<RecipeIndex> {
+        // Backwards, so the order doesn't come out right just by leaving it alone
+        let mut candidates: Vec<RecipeIndex> = recipes.enumerate().map(|(i, _)| i).collect();
+        candidates.reverse();
+        allocation.order(&mut candidates, recipes);
+        candidates
+    }
+
+    fn allocation(strategy: AllocationStrategy) -> Allocation {
+        Allocation {
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn strict_priority_breaks_ties_by_index() {
+        let recipes = recipes(&[0, 2, 0, 1]);
+        let order = ordered(&allocation(AllocationStrategy::StrictPriority), &recipes);
+        assert_eq!(
+            order,
+            [
+                RecipeIndex(1),
+                RecipeIndex(3),
+                RecipeIndex(0),
+                RecipeIndex(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn round_robin_serves_longest_waiting_first() {
+        let mut recipes = recipes(&[0, 0, 0]);
+        recipes.get_recipe_holder_mut(&RecipeIndex(0)).last_served = 5;
+        recipes.get_recipe_holder_mut(&RecipeIndex(1)).last_served = 3;
+        recipes.get_recipe_holder_mut(&RecipeIndex(2)).last_served = 4;
+        let order = ordered(&allocation(AllocationStrategy::RoundRobin), &recipes);
+        assert_eq!(order, [RecipeIndex(1), RecipeIndex(2), RecipeIndex(0)]);
+    }
+
+    #[test]
+    fn round_robin_respects_priority() {
+        let mut recipes = recipes(&[0, 1]);
+        recipes.get_recipe_holder_mut(&RecipeIndex(1)).last_served = 10;
+        let order = ordered(&allocation(AllocationStrategy::RoundRobin), &recipes);
+        assert_eq!(order, [RecipeIndex(1), RecipeIndex(0)]);
+    }
+
+    #[test]
+    fn round_robin_takes_turns() {
+        // Only one of three equal recipes can be served every tick, the way `tick_recipes`
+        // serves them when inputs are scarce
+        let mut recipes = recipes(&[0, 0, 0]);
+        let allocation = allocation(AllocationStrategy::RoundRobin);
+        let mut served = vec![];
+        for tick in 1..=6 {
+            let first = ordered(&allocation, &recipes)[0];
+            recipes.get_recipe_holder_mut(&first).last_served = tick;
+            served.push(first);
+        }
+        assert_eq!(
+            served,
+            [0, 1, 2, 0, 1, 2].map(RecipeIndex),
+            "equal recipes should take turns"
+        );
+    }
+
+    #[test]
+    fn proportional_favours_furthest_below_share() {
+        let mut recipes = recipes(&[0, 3, -1]);
+        // Shares are rate / (priority + 1), with negative priorities counting as 0
+        recipes.get_recipe_holder_mut(&RecipeIndex(0)).rate = 0.5;
+        recipes.get_recipe_holder_mut(&RecipeIndex(1)).rate = 1.0;
+        recipes.get_recipe_holder_mut(&RecipeIndex(2)).rate = 0.5;
+        let order = ordered(&allocation(AllocationStrategy::Proportional), &recipes);
+        assert_eq!(order, [RecipeIndex(1), RecipeIndex(0), RecipeIndex(2)]);
     }
 }
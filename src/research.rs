@@ -0,0 +1,311 @@
+use bevy::prelude::*;
+
+use crate::{
+    ingredient::{IngredientIndex, Ingredients},
+    quantity::Quantity,
+    recipe::Recipes,
+    unlock::{set_unlocked, Unlockable, Unlocked},
+    upgrade::Upgrades,
+};
+
+#[derive(Debug)]
+pub struct Research {
+    pub id: String,
+    pub name: String,
+    /// Ingredients consumed per second while the research is in progress
+    pub cost: Vec<(IngredientIndex, Quantity)>,
+    /// Seconds of progress needed to complete the research
+    pub duration: Quantity,
+    pub prerequisites: Vec<ResearchIndex>,
+    pub unlocks: Vec<Unlockable>,
+    /// Seconds of progress made so far
+    pub progress: f64,
+    pub completed: bool,
+}
+
+impl Research {
+    pub fn prerequisites_met(&self, researches: &Researches) -> bool {
+        self.prerequisites
+            .iter()
+            .all(|i| researches.get(*i).completed)
+    }
+
+    pub fn fraction_done(&self) -> f64 {
+        if self.duration.value() <= 0.0 {
+            return 1.0;
+        }
+        f64::min(self.progress / self.duration.value(), 1.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ResearchIndex(usize);
+
+impl ResearchIndex {
+    pub fn ix(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Researches {
+    researches: Vec<Research>,
+    /// The research currently making progress, if any
+    pub active: Option<ResearchIndex>,
+}
+
+impl Researches {
+    pub fn add_research(&mut self, research: Research) -> ResearchIndex {
+        let i = self.researches.len();
+        self.researches.push(research);
+        ResearchIndex(i)
+    }
+
+    pub fn get(&self, index: ResearchIndex) -> &Research {
+        &self.researches[index.0]
+    }
+
+    pub fn get_mut(&mut self, index: ResearchIndex) -> &mut Research {
+        &mut self.researches[index.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.researches.len()
+    }
+
+    pub fn enumerate(&self) -> impl Iterator<Item = (ResearchIndex, &Research)> {
+        self.researches
+            .iter()
+            .enumerate()
+            .map(|(i, r)| (ResearchIndex(i), r))
+    }
+
+    /// Length of the longest chain of prerequisites leading to each research.
+    /// Assumes the prerequisites form a DAG, which `GameBuilder` makes sure of.
+    pub fn depths(&self) -> Vec<usize> {
+        fn depth(researches: &Researches, i: usize, memo: &mut Vec<Option<usize>>) -> usize {
+            if let Some(d) = memo[i] {
+                return d;
+            }
+            let d = researches.researches[i]
+                .prerequisites
+                .iter()
+                .map(|p| depth(researches, p.0, memo) + 1)
+                .max()
+                .unwrap_or(0);
+            memo[i] = Some(d);
+            d
+        }
+
+        let mut memo = vec![None; self.researches.len()];
+        (0..self.researches.len())
+            .map(|i| depth(self, i, &mut memo))
+            .collect()
+    }
+}
+
+/// Looks for a cycle in a graph given as a list of edges for every vertex,
+/// returning the vertices along it in order
+pub fn find_cycle(edges: &[Vec<usize>]) -> Option<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Unvisited,
+        OnStack,
+        Done,
+    }
+
+    fn visit(
+        v: usize,
+        edges: &[Vec<usize>],
+        state: &mut [State],
+        stack: &mut Vec<usize>,
+    ) -> Option<Vec<usize>> {
+        state[v] = State::OnStack;
+        stack.push(v);
+
+        for &w in &edges[v] {
+            match state[w] {
+                State::OnStack => {
+                    let start = stack.iter().position(|&u| u == w).unwrap();
+                    return Some(stack[start..].to_vec());
+                }
+                State::Unvisited => {
+                    if let Some(cycle) = visit(w, edges, state, stack) {
+                        return Some(cycle);
+                    }
+                }
+                State::Done => {}
+            }
+        }
+
+        stack.pop();
+        state[v] = State::Done;
+        None
+    }
+
+    let mut state = vec![State::Unvisited; edges.len()];
+    let mut stack = vec![];
+    for v in 0..edges.len() {
+        if state[v] == State::Unvisited {
+            if let Some(cycle) = visit(v, edges, &mut state, &mut stack) {
+                return Some(cycle);
+            }
+        }
+    }
+
+    None
+}
+
+#[derive(Event, Debug)]
+pub enum ResearchEvent {
+    Start(ResearchIndex),
+    Stop,
+}
+
+fn process_research_events(
+    mut researches: ResMut<Researches>,
+    mut reader: EventReader<ResearchEvent>,
+) {
+    for event in reader.into_iter() {
+        match event {
+            ResearchEvent::Start(i) => {
+                let research = researches.get(*i);
+                if research.completed {
+                    warn!(
+                        "Tried to start research {}, which is already done",
+                        research.id
+                    );
+                    continue;
+                }
+                if !research.prerequisites_met(&researches) {
+                    warn!(
+                        "Tried to start research {} before its prerequisites were done",
+                        research.id
+                    );
+                    continue;
+                }
+
+                researches.active = Some(*i);
+            }
+            ResearchEvent::Stop => {
+                researches.active = None;
+            }
+        }
+    }
+}
+
+/// Consumes the cost of the active research and advances it, but only for as long as the
+/// cost can be paid in full
+fn tick_research(
+    mut researches: ResMut<Researches>,
+    mut ingredients: ResMut<Ingredients>,
+    mut recipes: ResMut<Recipes>,
+    mut upgrades: ResMut<Upgrades>,
+    mut writer: EventWriter<Unlocked>,
+    time: Res<Time>,
+) {
+    let Some(i) = researches.active else {
+        return;
+    };
+
+    let dt = time.delta_seconds_f64();
+    let research = researches.get_mut(i);
+
+    let remaining = f64::max(research.duration.value() - research.progress, 0.0);
+    let step = f64::min(dt, remaining);
+    let affordable = research
+        .cost
+        .iter()
+        .all(|(ty, amount)| ingredients.get(*ty).current >= amount.value() * step);
+    if !affordable {
+        return;
+    }
+
+    for (ty, amount) in &research.cost {
+        ingredients
+            .get_mut(*ty)
+            .spend_ingredient(amount.value() * step);
+    }
+    research.progress += step;
+
+    if research.progress < research.duration.value() {
+        return;
+    }
+
+    research.completed = true;
+    info!("Finished research {}", research.id);
+    for &target in &research.unlocks {
+        let already_unlocked =
+            set_unlocked(target, true, &mut ingredients, &mut recipes, &mut upgrades);
+        if !already_unlocked {
+            writer.send(Unlocked(target));
+        }
+    }
+    researches.active = None;
+}
+
+pub struct ResearchPlugin;
+
+impl Plugin for ResearchPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ResearchEvent>()
+            .init_resource::<Researches>()
+            .add_systems(Update, (process_research_events, tick_research).chain());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_cycle;
+
+    #[test]
+    fn no_cycle_in_a_dag() {
+        // A diamond reaches the same vertex twice, without going round in a circle
+        let edges = vec![vec![1, 2], vec![3], vec![3], vec![]];
+        assert_eq!(find_cycle(&edges), None);
+    }
+
+    #[test]
+    fn no_cycle_without_edges() {
+        assert_eq!(find_cycle(&[]), None);
+        assert_eq!(find_cycle(&[vec![], vec![]]), None);
+    }
+
+    #[test]
+    fn finds_a_self_loop() {
+        let edges = vec![vec![], vec![1]];
+        assert_eq!(find_cycle(&edges), Some(vec![1]));
+    }
+
+    #[test]
+    fn finds_a_cycle_in_order() {
+        // 0 leads into the cycle, but isn't part of it
+        let edges = vec![vec![1], vec![2], vec![3], vec![1]];
+        assert_eq!(find_cycle(&edges), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn cycle_edges_exist() {
+        let edges = vec![vec![2], vec![0], vec![3, 1], vec![]];
+        let cycle = find_cycle(&edges).unwrap();
+        for (k, &v) in cycle.iter().enumerate() {
+            let next = cycle[(k + 1) % cycle.len()];
+            assert!(edges[v].contains(&next), "{v} -> {next} isn't an edge");
+        }
+    }
+
+    #[test]
+    fn breaking_cycles_terminates() {
+        // The same way `GameBuilder::build` breaks them, by dropping the edge closing the cycle
+        let mut edges = vec![vec![1, 2], vec![2, 0], vec![0, 1]];
+        let mut removed = 0;
+        while let Some(cycle) = find_cycle(&edges) {
+            let last = cycle[cycle.len() - 1];
+            edges[last].retain(|w| *w != cycle[0]);
+            removed += 1;
+            assert!(removed <= 6, "kept finding cycles");
+        }
+        assert_eq!(find_cycle(&edges), None);
+        assert!(edges.iter().map(Vec::len).sum::<usize>() >= 3);
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::bookmark::CameraBookmark;
+
+const SAVE_PATH: &str = "save.ron";
+
+/// Everything about the player's game that outlives a session
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    /// Positions of nodes placed by hand, keyed by ingredient or recipe id
+    pub node_positions: BTreeMap<String, [f32; 2]>,
+    pub bookmarks: BTreeMap<u8, CameraBookmark>,
+    /// Ids of the ingredients and recipes pinned to the watch list, in the order they were pinned
+    pub watch_list: Vec<String>,
+    /// Ids of the ingredients and recipes whose nodes the player hid
+    pub hidden_nodes: BTreeSet<String>,
+}
+
+fn load_save(mut commands: Commands) {
+    let save_data = match std::fs::read_to_string(SAVE_PATH) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            info!("No save file found at {}, starting a new game", SAVE_PATH);
+            SaveData::default()
+        }
+        Err(e) => {
+            error!("Couldn't read save file {}: {}", SAVE_PATH, e);
+            SaveData::default()
+        }
+        Ok(s) => match ron::from_str(&s) {
+            Ok(save_data) => save_data,
+            Err(e) => {
+                error!("Couldn't parse save file {}: {}", SAVE_PATH, e);
+                SaveData::default()
+            }
+        },
+    };
+
+    commands.insert_resource(save_data);
+}
+
+fn write_save(save_data: Res<SaveData>) {
+    if !save_data.is_changed() || save_data.is_added() {
+        return;
+    }
+
+    let s = match ron::ser::to_string_pretty(&*save_data, ron::ser::PrettyConfig::default()) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Couldn't serialize save data: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = std::fs::write(SAVE_PATH, s) {
+        error!("Couldn't write save file {}: {}", SAVE_PATH, e);
+    }
+}
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveData>()
+            .add_systems(PreStartup, load_save)
+            .add_systems(Last, write_save);
+    }
+}
@@ -0,0 +1,260 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts, EguiSet};
+use bevy_mod_picking::prelude::*;
+
+use crate::{
+    camera::MainCamera,
+    context_menu::automated_recipes,
+    ingredient::Ingredients,
+    node::{Node, NodeType},
+    recipe::{RecipeIndex, Recipes},
+    save::SaveData,
+    utils,
+};
+
+/// The nodes the player has selected, in the order they were selected
+#[derive(Debug, Default, Resource)]
+pub struct Selection {
+    nodes: Vec<NodeType>,
+}
+
+impl Selection {
+    /// Replaces the selection with just this node
+    pub fn select(&mut self, ty: NodeType) {
+        self.nodes.clear();
+        self.nodes.push(ty);
+    }
+
+    pub fn add(&mut self, ty: NodeType) {
+        if !self.contains(ty) {
+            self.nodes.push(ty);
+        }
+    }
+
+    pub fn toggle(&mut self, ty: NodeType) {
+        match self.nodes.iter().position(|n| *n == ty) {
+            Some(i) => {
+                self.nodes.remove(i);
+            }
+            None => self.nodes.push(ty),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+
+    pub fn contains(&self, ty: NodeType) -> bool {
+        self.nodes.contains(&ty)
+    }
+
+    /// The most recently selected node, which the node panel shows
+    pub fn primary(&self) -> Option<NodeType> {
+        self.nodes.last().copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = NodeType> + '_ {
+        self.nodes.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+/// Shift-dragging on empty space draws a box, and every visible node inside it is added to
+/// the selection when the button is released
+#[allow(clippy::too_many_arguments)]
+fn box_select(
+    mut contexts: EguiContexts,
+    window_query: Query<(Entity, &Window), With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    node_query: Query<(&GlobalTransform, &Node)>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut pointer_down: EventReader<Pointer<Down>>,
+    mut selection: ResMut<Selection>,
+    mut corners: Local<Option<(Vec2, Vec2)>>,
+) {
+    // Boxes smaller than this, in pixels, are treated as clicks
+    const MIN_SIZE: f32 = 4.0;
+
+    let Ok((window_e, window)) = window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(window_e) else {
+        return;
+    };
+
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let pressed_on_entity = pointer_down.iter().count() > 0;
+    if buttons.just_pressed(MouseButton::Left)
+        && shift
+        && !pressed_on_entity
+        && !ctx.is_pointer_over_area()
+    {
+        *corners = window.cursor_position().map(|p| (p, p));
+    }
+
+    let Some((start, mut end)) = *corners else {
+        return;
+    };
+    if let Some(cursor) = window.cursor_position() {
+        end = cursor;
+    }
+    let rect = Rect::from_corners(start, end);
+
+    if buttons.pressed(MouseButton::Left) {
+        *corners = Some((start, end));
+
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("box select"),
+        ));
+        let rect = egui::Rect::from_min_max(
+            egui::pos2(rect.min.x, rect.min.y),
+            egui::pos2(rect.max.x, rect.max.y),
+        );
+        painter.rect_filled(rect, 0.0, egui::Color32::from_white_alpha(16));
+        painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, egui::Color32::WHITE));
+        return;
+    }
+
+    *corners = None;
+    if rect.width() < MIN_SIZE && rect.height() < MIN_SIZE {
+        return;
+    }
+
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    for (transform, node) in node_query.iter() {
+        if !node.visible {
+            continue;
+        }
+        let inside = camera
+            .world_to_viewport(camera_transform, transform.translation())
+            .is_some_and(|p| rect.contains(p));
+        if inside {
+            selection.add(node.ty);
+        }
+    }
+}
+
+/// Every automatic recipe related to a selected node, without duplicates
+fn selected_recipes(selection: &Selection, recipes: &Recipes) -> Vec<RecipeIndex> {
+    let mut related = vec![];
+    for ty in selection.iter() {
+        for i in automated_recipes(ty, recipes) {
+            if !related.contains(&i) {
+                related.push(i);
+            }
+        }
+    }
+    related
+}
+
+/// Summarizes the selection when more than one node is selected, with operations on all of them
+fn draw_selection(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<PrimaryWindow>>,
+    mut selection: ResMut<Selection>,
+    ingredients: Res<Ingredients>,
+    mut recipes: ResMut<Recipes>,
+    mut save_data: ResMut<SaveData>,
+) {
+    if selection.len() < 2 {
+        return;
+    }
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    let related = selected_recipes(&selection, &recipes);
+    let any_automated = related
+        .iter()
+        .any(|i| recipes.get_recipe_holder(i).automation_enabled);
+
+    let mut toggle_automation = false;
+    let mut hide = false;
+    let mut clear = false;
+    egui::Window::new("Selection")
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label(format!("{} nodes selected", selection.len()));
+            ui.weak("Drag a selected node to move them all");
+
+            let mut total_stock = 0.0;
+            let mut total_rate = 0.0;
+            egui::Grid::new("selection summary")
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    for ty in selection.iter() {
+                        match ty {
+                            NodeType::Ingredient(i) => {
+                                let ingredient = ingredients.get(i);
+                                let rate = recipes.net_rate(i);
+                                total_stock += ingredient.current;
+                                total_rate += rate;
+                                ui.label(&ingredient.name);
+                                ui.label(utils::format_number(ingredient.current));
+                                ui.label(utils::format_rate(rate));
+                            }
+                            NodeType::Recipe(i) => {
+                                let holder = recipes.get_recipe_holder(&i);
+                                ui.label(&holder.recipe.id);
+                                ui.label("");
+                                ui.label(format!("{:.2} cycles/s", holder.rate));
+                            }
+                        }
+                        ui.end_row();
+                    }
+
+                    ui.strong("Total");
+                    ui.strong(utils::format_number(total_stock));
+                    ui.strong(utils::format_rate(total_rate));
+                    ui.end_row();
+                });
+
+            ui.horizontal(|ui| {
+                if !related.is_empty() {
+                    let label = match any_automated {
+                        true => "Disable automation",
+                        false => "Enable automation",
+                    };
+                    toggle_automation = ui.button(label).clicked();
+                }
+                hide = ui.button("Hide").clicked();
+                clear = ui.button("Clear selection").clicked();
+            });
+        });
+
+    if toggle_automation {
+        for i in related.iter() {
+            recipes.get_recipe_holder_mut(i).automation_enabled = !any_automated;
+        }
+    }
+    if hide {
+        for ty in selection.iter() {
+            let id = ty.id(&ingredients, &recipes).to_string();
+            save_data.hidden_nodes.insert(id);
+        }
+    }
+    if hide || clear {
+        selection.clear();
+    }
+}
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Selection>()
+            .add_systems(Update, box_select)
+            .add_systems(PostUpdate, draw_selection.after(EguiSet::InitContexts));
+    }
+}
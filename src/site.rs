@@ -0,0 +1,363 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiSet};
+
+use crate::{
+    camera::{CameraFocus, MainCamera},
+    ingredient::{IngredientIndex, Ingredients},
+    quantity::Quantity,
+    ui::OpenWindows,
+    utils,
+};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct SiteIndex(usize);
+
+impl SiteIndex {
+    pub fn ix(&self) -> usize {
+        self.0
+    }
+}
+
+/// A factory with its own stock of ingredients and its own recipes, laid out around `origin`
+/// on the ground plane
+#[derive(Debug)]
+pub struct Site {
+    pub name: String,
+    pub origin: Vec2,
+}
+
+#[derive(Resource, Default)]
+pub struct Sites {
+    sites: Vec<Site>,
+    /// The site the player is looking at, whose stock the ingredient panel shows
+    active: SiteIndex,
+}
+
+impl Sites {
+    pub fn add_site(&mut self, site: Site) -> SiteIndex {
+        let i = self.sites.len();
+        self.sites.push(site);
+        SiteIndex(i)
+    }
+
+    pub fn get(&self, index: SiteIndex) -> &Site {
+        &self.sites[index.0]
+    }
+
+    pub fn enumerate(&self) -> impl Iterator<Item = (SiteIndex, &Site)> {
+        self.sites
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (SiteIndex(i), s))
+    }
+
+    pub fn len(&self) -> usize {
+        self.sites.len()
+    }
+
+    pub fn active(&self) -> SiteIndex {
+        self.active
+    }
+
+    /// Where nodes of the site gather, or the origin for sites that don't exist
+    pub fn origin(&self, index: SiteIndex) -> Vec2 {
+        self.sites.get(index.0).map_or(Vec2::ZERO, |s| s.origin)
+    }
+}
+
+/// Carries an ingredient from the stock of one site to another, one shipment at a time
+#[derive(Debug)]
+pub struct ShippingRoute {
+    pub from: SiteIndex,
+    pub to: SiteIndex,
+    /// The ingredient taken from the stock of `from`
+    pub source: IngredientIndex,
+    /// The same ingredient in the stock of `to`
+    pub destination: IngredientIndex,
+    /// Amount carried by every shipment
+    pub amount: Quantity,
+    /// Seconds a shipment takes to arrive
+    pub travel_time: Quantity,
+    /// Paid from the stock of `from` for every shipment
+    pub cost: Vec<(IngredientIndex, Quantity)>,
+    /// Sends a shipment off whenever the route is free and the stock allows
+    pub automatic: bool,
+    /// Seconds until the shipment on its way arrives, if there is one
+    pub remaining: Option<f64>,
+}
+
+impl ShippingRoute {
+    pub fn can_dispatch(&self, ingredients: &Ingredients) -> bool {
+        self.remaining.is_none()
+            && ingredients.get(self.source).current >= self.amount.value()
+            && self
+                .cost
+                .iter()
+                .all(|&(ty, amount)| ingredients.get(ty).current >= amount.value())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ShippingRouteIndex(usize);
+
+#[derive(Resource, Default)]
+pub struct ShippingRoutes {
+    routes: Vec<ShippingRoute>,
+}
+
+impl ShippingRoutes {
+    pub fn add_route(&mut self, route: ShippingRoute) -> ShippingRouteIndex {
+        let i = self.routes.len();
+        self.routes.push(route);
+        ShippingRouteIndex(i)
+    }
+
+    pub fn get_mut(&mut self, index: ShippingRouteIndex) -> &mut ShippingRoute {
+        &mut self.routes[index.0]
+    }
+
+    pub fn enumerate(&self) -> impl Iterator<Item = (ShippingRouteIndex, &ShippingRoute)> {
+        self.routes
+            .iter()
+            .enumerate()
+            .map(|(i, r)| (ShippingRouteIndex(i), r))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+#[derive(Event, Debug)]
+pub enum SiteEvent {
+    /// Makes the site the active one and moves the camera over to it
+    Switch(SiteIndex),
+    /// Sends a shipment off along the route
+    Ship(ShippingRouteIndex),
+}
+
+fn dispatch_automatic_routes(
+    routes: Res<ShippingRoutes>,
+    ingredients: Res<Ingredients>,
+    mut writer: EventWriter<SiteEvent>,
+) {
+    for (i, route) in routes.enumerate() {
+        if route.automatic && route.can_dispatch(&ingredients) {
+            writer.send(SiteEvent::Ship(i));
+        }
+    }
+}
+
+fn process_site_events(
+    mut reader: EventReader<SiteEvent>,
+    mut sites: ResMut<Sites>,
+    mut routes: ResMut<ShippingRoutes>,
+    mut ingredients: ResMut<Ingredients>,
+    mut camera_query: Query<&mut CameraFocus, With<MainCamera>>,
+) {
+    for event in reader.iter() {
+        match event {
+            SiteEvent::Switch(i) => {
+                if i.ix() >= sites.len() {
+                    warn!("Tried to switch to a site that doesn't exist");
+                    continue;
+                }
+                sites.active = *i;
+                if let Ok(mut focus) = camera_query.get_single_mut() {
+                    let origin = sites.get(*i).origin;
+                    focus.set_target(Vec3::new(origin.x, 0.0, origin.y));
+                }
+            }
+            SiteEvent::Ship(i) => {
+                let route = routes.get_mut(*i);
+                // Sent off manually and automatically in the same frame
+                if !route.can_dispatch(&ingredients) {
+                    continue;
+                }
+
+                ingredients
+                    .get_mut(route.source)
+                    .spend_ingredient(route.amount.value());
+                for (ty, amount) in &route.cost {
+                    ingredients.get_mut(*ty).spend_ingredient(amount.value());
+                }
+                route.remaining = Some(route.travel_time.value());
+            }
+        }
+    }
+}
+
+fn tick_shipping_routes(
+    mut routes: ResMut<ShippingRoutes>,
+    mut ingredients: ResMut<Ingredients>,
+    time: Res<Time>,
+) {
+    for route in routes.routes.iter_mut() {
+        let Some(remaining) = route.remaining.as_mut() else {
+            continue;
+        };
+        *remaining -= time.delta_seconds_f64();
+        if *remaining <= 0.0 {
+            let ingredient = ingredients.get_mut(route.destination);
+            let lost = ingredient.add_ingredient(route.amount.value());
+            if lost > 0.0 {
+                info!(
+                    "Shipped more {} than fits, {} was lost",
+                    ingredient.name, lost
+                );
+            }
+            route.remaining = None;
+        }
+    }
+}
+
+/// Buttons for every site along the top of the screen, only shown once there's more than one
+fn draw_site_switcher(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
+    sites: Res<Sites>,
+    routes: Res<ShippingRoutes>,
+    mut open_windows: ResMut<OpenWindows>,
+    mut writer: EventWriter<SiteEvent>,
+) {
+    if sites.len() < 2 {
+        return;
+    }
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    egui::Area::new("site switcher")
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 8.0])
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for (i, site) in sites.enumerate() {
+                    if ui
+                        .selectable_label(i == sites.active(), &site.name)
+                        .clicked()
+                    {
+                        writer.send(SiteEvent::Switch(i));
+                    }
+                }
+                if !routes.is_empty() {
+                    ui.separator();
+                    let mut open = open_windows.shipping;
+                    if ui.toggle_value(&mut open, "Shipping").changed() {
+                        open_windows.shipping = open;
+                    }
+                }
+            });
+        });
+}
+
+fn draw_shipping(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
+    mut open_windows: ResMut<OpenWindows>,
+    sites: Res<Sites>,
+    mut routes: ResMut<ShippingRoutes>,
+    ingredients: Res<Ingredients>,
+    mut writer: EventWriter<SiteEvent>,
+) {
+    if !open_windows.shipping {
+        return;
+    }
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    let mut toggled = None;
+    egui::Window::new("Shipping")
+        .open(&mut open_windows.shipping)
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("shipping routes")
+                .num_columns(4)
+                .striped(true)
+                .show(ui, |ui| {
+                    for (i, route) in routes.enumerate() {
+                        let mut label = String::new();
+                        utils::write_format_quantity(&mut label, route.amount).unwrap();
+                        label.push(' ');
+                        label.push_str(&ingredients.get(route.source).name);
+                        label.push_str(&format!(
+                            ": {} to {}",
+                            sites.get(route.from).name,
+                            sites.get(route.to).name
+                        ));
+                        let mut hover =
+                            format!("Takes {}s", utils::format_number(route.travel_time.value()));
+                        for (ty, q) in route.cost.iter() {
+                            hover.push_str(", costs ");
+                            utils::write_format_quantity(&mut hover, *q).unwrap();
+                            hover.push(' ');
+                            hover.push_str(&ingredients.get(*ty).name);
+                        }
+                        ui.label(label).on_hover_text(hover);
+
+                        match route.remaining {
+                            Some(remaining) => {
+                                let progress = 1.0 - remaining / route.travel_time.value();
+                                ui.add(
+                                    egui::ProgressBar::new(progress as f32)
+                                        .desired_width(80.0)
+                                        .text(format!("{}s", utils::format_number(remaining))),
+                                );
+                            }
+                            None => {
+                                ui.label("");
+                            }
+                        }
+
+                        let mut automatic = route.automatic;
+                        if ui.checkbox(&mut automatic, "Auto").changed() {
+                            toggled = Some(i);
+                        }
+                        if ui
+                            .add_enabled(
+                                route.can_dispatch(&ingredients),
+                                egui::Button::new("Ship"),
+                            )
+                            .clicked()
+                        {
+                            writer.send(SiteEvent::Ship(i));
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+
+    if let Some(i) = toggled {
+        let route = routes.get_mut(i);
+        route.automatic = !route.automatic;
+    }
+}
+
+pub struct SitePlugin;
+
+impl Plugin for SitePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SiteEvent>()
+            .init_resource::<Sites>()
+            .init_resource::<ShippingRoutes>()
+            .add_systems(
+                Update,
+                (
+                    dispatch_automatic_routes,
+                    process_site_events,
+                    tick_shipping_routes,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                PostUpdate,
+                (draw_site_switcher, draw_shipping).after(EguiSet::InitContexts),
+            );
+    }
+}
@@ -0,0 +1,211 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts, EguiSet};
+use bevy_mod_picking::prelude::*;
+
+use crate::{
+    ingredient::{IngredientIndex, Ingredients},
+    link::LinkFlow,
+    node::{Node, NodeType},
+    recipe::{RecipeIndex, Recipes},
+    transport::{StockTotals, Transit},
+    ui::stock_breakdown,
+    utils,
+};
+
+/// The node or link under the pointer, if any
+#[derive(Resource, Debug, Default)]
+pub struct Hovered {
+    pub entity: Option<Entity>,
+}
+
+fn track_hovered(
+    mut over_reader: EventReader<Pointer<Over>>,
+    mut out_reader: EventReader<Pointer<Out>>,
+    mut hovered: ResMut<Hovered>,
+) {
+    for ev in out_reader.iter() {
+        if hovered.entity == Some(ev.target) {
+            hovered.entity = None;
+        }
+    }
+
+    for ev in over_reader.iter() {
+        hovered.entity = Some(ev.target);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_tooltip(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<PrimaryWindow>>,
+    hovered: Res<Hovered>,
+    node_query: Query<&Node>,
+    flow_query: Query<(&LinkFlow, Option<&Transit>)>,
+    ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    totals: Res<StockTotals>,
+) {
+    let Some(e) = hovered.entity else {
+        return;
+    };
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+    // Whatever is under the pointer in the scene is covered by the UI
+    if ctx.is_pointer_over_area() {
+        return;
+    }
+
+    let id = egui::Id::new("scene tooltip");
+    if let Ok(node) = node_query.get(e) {
+        if !node.visible {
+            return;
+        }
+        egui::show_tooltip_at_pointer(ctx, id, |ui| match node.ty {
+            NodeType::Ingredient(i) => ingredient_tooltip(ui, i, &ingredients, &recipes, &totals),
+            NodeType::Recipe(i) => recipe_tooltip(ui, i, &ingredients, &recipes),
+        });
+    } else if let Ok((flow, transit)) = flow_query.get(e) {
+        egui::show_tooltip_at_pointer(ctx, id, |ui| {
+            link_tooltip(ui, flow, transit, &ingredients, &recipes)
+        });
+    }
+}
+
+fn ingredient_tooltip(
+    ui: &mut egui::Ui,
+    i: IngredientIndex,
+    ingredients: &Ingredients,
+    recipes: &Recipes,
+    totals: &StockTotals,
+) {
+    // Number of producing recipes listed
+    const TOP_PRODUCERS: usize = 3;
+
+    let ingredient = ingredients.get(i);
+    ui.strong(&ingredient.name);
+
+    let mut stock = format!("Stock: {}", utils::format_number(ingredient.current));
+    if let Some(capacity) = ingredient.capacity {
+        stock.push_str(" / ");
+        utils::write_format_quantity(&mut stock, capacity).unwrap();
+    }
+    ui.label(stock);
+    let breakdown = totals.get(i);
+    if breakdown.total() > breakdown.stored {
+        ui.label(stock_breakdown(breakdown));
+    }
+
+    let net_rate = recipes.net_rate(i);
+    let sign = if net_rate > 0.0 { "+" } else { "" };
+    ui.label(format!("Net: {}{}", sign, utils::format_rate(net_rate)));
+
+    let mut producers: Vec<(&str, f64)> = recipes
+        .enumerate()
+        .filter(|(_, holder)| {
+            holder.unlocked && holder.recipe.output.iter().any(|(ty, _)| *ty == i)
+        })
+        .map(|(_, holder)| (holder.recipe.id.as_str(), holder.production_rate(i)))
+        .collect();
+    if producers.is_empty() {
+        return;
+    }
+    producers.sort_by(|a, b| f64::total_cmp(&b.1, &a.1));
+
+    ui.separator();
+    ui.weak("Top producers");
+    for (id, rate) in producers.into_iter().take(TOP_PRODUCERS) {
+        ui.label(format!("{}: {}", id, utils::format_rate(rate)));
+    }
+}
+
+fn recipe_tooltip(ui: &mut egui::Ui, i: RecipeIndex, ingredients: &Ingredients, recipes: &Recipes) {
+    let holder = recipes.get_recipe_holder(&i);
+    ui.strong(&holder.recipe.id);
+
+    for (label, list) in [("In", &holder.recipe.input), ("Out", &holder.recipe.output)] {
+        if list.is_empty() {
+            continue;
+        }
+        let mut line = format!("{}: ", label);
+        for (n, (ty, q)) in list.iter().enumerate() {
+            if n > 0 {
+                line.push_str(", ");
+            }
+            utils::write_format_quantity(&mut line, *q).unwrap();
+            line.push(' ');
+            line.push_str(&ingredients.get(*ty).name);
+        }
+        ui.label(line);
+    }
+
+    if let Some(buffer) = &holder.buffer {
+        let mut line = "Input buffer: ".to_string();
+        for (k, ((ty, _), stored)) in holder.recipe.input.iter().zip(buffer).enumerate() {
+            if k > 0 {
+                line.push_str(", ");
+            }
+            utils::write_format_number(&mut line, *stored).unwrap();
+            line.push_str(" / ");
+            utils::write_format_number(&mut line, holder.buffer_capacity(k)).unwrap();
+            line.push(' ');
+            line.push_str(&ingredients.get(*ty).name);
+        }
+        if !buffer.is_empty() {
+            ui.label(line);
+        }
+    }
+
+    if holder.queued > 0 {
+        ui.label(format!("{} queued", holder.queued));
+    }
+
+    if holder.stalled(ingredients) && holder.output_target_reached(ingredients) {
+        ui.weak("Target reached");
+    } else if holder.stalled(ingredients) {
+        ui.weak("Stalled");
+    } else {
+        ui.label(format!("{:.2} cycles/s", holder.rate));
+    }
+}
+
+fn link_tooltip(
+    ui: &mut egui::Ui,
+    flow: &LinkFlow,
+    transit: Option<&Transit>,
+    ingredients: &Ingredients,
+    recipes: &Recipes,
+) {
+    let recipe = recipes.get_recipe(&flow.recipe);
+    ui.strong(&recipe.id);
+    ui.label(format!(
+        "{} {}",
+        utils::format_rate(flow.throughput),
+        ingredients.get(flow.ingredient).name
+    ));
+    if flow.stalled {
+        ui.weak("Stalled");
+    }
+    if let Some(transit) = transit {
+        if transit.in_transit() > 0.0 || transit.backlog > 0.0 {
+            ui.label(format!(
+                "In transit: {}, waiting: {}",
+                utils::format_number(transit.in_transit()),
+                utils::format_number(transit.backlog)
+            ));
+        }
+    }
+}
+
+pub struct TooltipPlugin;
+
+impl Plugin for TooltipPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Hovered>()
+            .add_systems(Update, track_hovered)
+            .add_systems(PostUpdate, draw_tooltip.after(EguiSet::InitContexts));
+    }
+}
@@ -0,0 +1,441 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{
+    belt::{Belt, BeltTiers},
+    ingredient::{IngredientIndex, Ingredients},
+    link::{Link, LinkFlow},
+    node::{Node, NodeRegistry, NodeType},
+    recipe::{process_recipe_events, Allocation, RecipeCompleted, RecipeIndex, Recipes},
+};
+
+/// In logistics mode recipe outputs travel along the links to their ingredient, and are only
+/// added to the stock once they arrive. Recipes take their inputs from their own input buffers,
+/// which their input links fill from the ingredients' stock. Ingredient nodes don't get buffers
+/// of their own, each ingredient still has a single stock per site.
+#[derive(Resource, Debug)]
+pub struct Logistics {
+    pub enabled: bool,
+    /// World units per second items travel along links, so longer links take longer
+    pub belt_speed: f32,
+}
+
+impl Default for Logistics {
+    fn default() -> Self {
+        Logistics {
+            enabled: false,
+            belt_speed: 4.0,
+        }
+    }
+}
+
+/// Where the amount of an ingredient is kept, for the aggregate shown in the UI
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StockBreakdown {
+    /// In the ingredient's own stock
+    pub stored: f64,
+    /// In the buffers of the recipes using it
+    pub buffered: f64,
+    /// On the links, including items waiting to be sent off
+    pub in_transit: f64,
+}
+
+impl StockBreakdown {
+    pub fn total(&self) -> f64 {
+        self.stored + self.buffered + self.in_transit
+    }
+}
+
+/// Everything owned of each ingredient, wherever it is
+#[derive(Resource, Debug, Default)]
+pub struct StockTotals {
+    ingredients: Vec<StockBreakdown>,
+}
+
+impl StockTotals {
+    pub fn get(&self, ty: IngredientIndex) -> StockBreakdown {
+        self.ingredients.get(ty.ix()).copied().unwrap_or_default()
+    }
+}
+
+/// Items arriving at the stock of an ingredient
+#[derive(Event, Debug)]
+pub struct Delivery {
+    pub ingredient: IngredientIndex,
+    pub amount: f64,
+}
+
+#[derive(Debug)]
+struct Shipment {
+    amount: f64,
+    /// Fraction of the link already travelled
+    progress: f32,
+    visual: Entity,
+}
+
+/// Items on their way along a link from a recipe to the ingredient it produces
+#[derive(Component, Debug)]
+pub struct Transit {
+    /// Items waiting to be sent off because the link's belt is at capacity
+    pub backlog: f64,
+    shipments: VecDeque<Shipment>,
+}
+
+impl Transit {
+    fn new() -> Self {
+        Transit {
+            backlog: 0.0,
+            shipments: VecDeque::new(),
+        }
+    }
+
+    /// Items sent off that haven't arrived yet
+    pub fn in_transit(&self) -> f64 {
+        self.shipments.iter().map(|s| s.amount).sum()
+    }
+}
+
+#[derive(Component, Debug)]
+struct TransitItem;
+
+/// A link carrying an ingredient into the recipe using it, rather than out of the recipe
+#[derive(Component, Debug)]
+pub struct Supply;
+
+#[derive(Resource)]
+struct TransitVisuals {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup_transit_visuals(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(TransitVisuals {
+        mesh: meshes.add(shape::Cube { size: 0.25 }.into()),
+        material: materials.add(Color::ORANGE.into()),
+    });
+}
+
+/// Gives every link leading out of a recipe somewhere to hold items in transit, and marks the
+/// links leading into one as supplying its buffer
+fn add_transit(
+    mut commands: Commands,
+    query: Query<(Entity, &Link, &LinkFlow), Added<LinkFlow>>,
+    node_registry: Res<NodeRegistry>,
+) {
+    for (e, link, flow) in query.iter() {
+        let recipe_node = node_registry.get(&NodeType::Recipe(flow.recipe));
+        if recipe_node == Some(&link.from) {
+            commands.entity(e).insert(Transit::new());
+        } else if recipe_node == Some(&link.to) {
+            commands.entity(e).insert(Supply);
+        }
+    }
+}
+
+/// Gives recipes empty buffers when logistics is turned on, and puts whatever is left in them
+/// back into stock when it's turned off
+fn sync_buffers(
+    logistics: Res<Logistics>,
+    mut recipes: ResMut<Recipes>,
+    mut ingredients: ResMut<Ingredients>,
+) {
+    if !logistics.is_changed() {
+        return;
+    }
+
+    let out_of_sync: Vec<RecipeIndex> = recipes
+        .enumerate()
+        .filter(|(_, holder)| logistics.enabled != holder.buffer.is_some())
+        .map(|(i, _)| i)
+        .collect();
+    for i in out_of_sync {
+        let holder = recipes.get_recipe_holder_mut(&i);
+        match logistics.enabled {
+            true => holder.buffer = Some(vec![0.0; holder.recipe.input.len()]),
+            false => {
+                let Some(buffer) = holder.buffer.take() else {
+                    continue;
+                };
+                for ((ty, _), stored) in holder.recipe.input.iter().zip(buffer) {
+                    let ingredient = ingredients.get_mut(*ty);
+                    let lost = ingredient.add_ingredient(stored);
+                    if lost > 0.0 {
+                        info!(
+                            "Emptying the buffer of {} returned more {} than fits, {} was lost",
+                            holder.recipe.id, ingredient.name, lost
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Moves ingredients from their stock into the buffers of the recipes using them, as fast as
+/// the belt of the link between them allows
+fn fill_buffers(
+    query: Query<(&LinkFlow, &Belt), With<Supply>>,
+    tiers: Res<BeltTiers>,
+    mut recipes: ResMut<Recipes>,
+    mut ingredients: ResMut<Ingredients>,
+    allocation: Res<Allocation>,
+    logistics: Res<Logistics>,
+    time: Res<Time>,
+) {
+    if !logistics.enabled {
+        return;
+    }
+
+    // When there isn't enough stock for every buffer, the allocation strategy decides who gets
+    // it first, the same way it does for recipes starting without logistics
+    let mut order: Vec<RecipeIndex> = query.iter().map(|(flow, _)| flow.recipe).collect();
+    order.sort();
+    order.dedup();
+    allocation.order(&mut order, &recipes);
+    let mut links: Vec<(&LinkFlow, &Belt)> = query.iter().collect();
+    links.sort_by_key(|(flow, _)| order.iter().position(|i| *i == flow.recipe));
+
+    for (flow, belt) in links {
+        let mut budget = belt.capacity(&tiers) * time.delta_seconds_f64();
+        let holder = recipes.get_recipe_holder_mut(&flow.recipe);
+        let capacities: Vec<f64> = (0..holder.recipe.input.len())
+            .map(|k| holder.buffer_capacity(k))
+            .collect();
+        let available: Vec<f64> = (0..holder.recipe.input.len())
+            .map(|k| holder.available(k, &ingredients))
+            .collect();
+        let Some(buffer) = holder.buffer.as_mut() else {
+            continue;
+        };
+
+        for (k, (ty, _)) in holder.recipe.input.iter().enumerate() {
+            if *ty != flow.ingredient {
+                continue;
+            }
+            let amount = f64::min(budget, f64::min(capacities[k] - buffer[k], available[k]));
+            if amount <= 0.0 {
+                continue;
+            }
+            ingredients.get_mut(*ty).spend_ingredient(amount);
+            buffer[k] += amount;
+            budget -= amount;
+        }
+    }
+}
+
+/// Puts the outputs of finished recipes on the links leading to their ingredients
+fn load_outputs(
+    mut reader: EventReader<RecipeCompleted>,
+    mut link_query: Query<(&LinkFlow, &mut Transit)>,
+    recipes: Res<Recipes>,
+    logistics: Res<Logistics>,
+    mut writer: EventWriter<Delivery>,
+) {
+    if !logistics.enabled {
+        reader.clear();
+        return;
+    }
+
+    for RecipeCompleted(i) in reader.iter() {
+        for &(ty, amount) in recipes.get_recipe(i).output.iter() {
+            let link = link_query
+                .iter_mut()
+                .find(|(flow, _)| flow.recipe == *i && flow.ingredient == ty);
+            match link {
+                Some((_, mut transit)) => transit.backlog += amount.value(),
+                // Nothing to carry it, so it arrives right away
+                None => writer.send(Delivery {
+                    ingredient: ty,
+                    amount: amount.value(),
+                }),
+            }
+        }
+    }
+}
+
+/// Sends waiting items off along their link, as fast as its belt allows
+fn dispatch_shipments(
+    mut commands: Commands,
+    mut query: Query<(&mut Transit, &Belt)>,
+    tiers: Res<BeltTiers>,
+    visuals: Res<TransitVisuals>,
+    time: Res<Time>,
+) {
+    // Items sent off shortly after the previous shipment join it, rather than travelling alone
+    const MERGE_PROGRESS: f32 = 0.1;
+
+    for (mut transit, belt) in query.iter_mut() {
+        if transit.backlog <= 0.0 {
+            continue;
+        }
+
+        let capacity = belt.capacity(&tiers);
+        let amount = f64::min(transit.backlog, capacity * time.delta_seconds_f64());
+        transit.backlog -= amount;
+
+        match transit.shipments.back_mut() {
+            Some(last) if last.progress < MERGE_PROGRESS => last.amount += amount,
+            _ => {
+                let visual = commands
+                    .spawn((
+                        PbrBundle {
+                            mesh: visuals.mesh.clone(),
+                            material: visuals.material.clone(),
+                            visibility: Visibility::Hidden,
+                            ..Default::default()
+                        },
+                        TransitItem,
+                    ))
+                    .id();
+                transit.shipments.push_back(Shipment {
+                    amount,
+                    progress: 0.0,
+                    visual,
+                });
+            }
+        }
+    }
+}
+
+fn move_shipments(
+    mut commands: Commands,
+    mut link_query: Query<(&Link, &LinkFlow, &mut Transit)>,
+    node_query: Query<(&Transform, &Node), Without<TransitItem>>,
+    mut item_query: Query<(&mut Transform, &mut Visibility), With<TransitItem>>,
+    mut writer: EventWriter<Delivery>,
+    logistics: Res<Logistics>,
+    time: Res<Time>,
+) {
+    for (link, flow, mut transit) in link_query.iter_mut() {
+        let (Ok((a, node_a)), Ok((b, node_b))) =
+            (node_query.get(link.from), node_query.get(link.to))
+        else {
+            continue;
+        };
+
+        let length = a.translation.distance(b.translation);
+        let advance = logistics.belt_speed * time.delta_seconds() / f32::max(length, f32::EPSILON);
+        for shipment in transit.shipments.iter_mut() {
+            shipment.progress += advance;
+        }
+
+        while transit
+            .shipments
+            .front()
+            .is_some_and(|shipment| shipment.progress >= 1.0)
+        {
+            let shipment = transit.shipments.pop_front().unwrap();
+            commands.entity(shipment.visual).despawn();
+            writer.send(Delivery {
+                ingredient: flow.ingredient,
+                amount: shipment.amount,
+            });
+        }
+
+        for shipment in transit.shipments.iter() {
+            let Ok((mut transform, mut visibility)) = item_query.get_mut(shipment.visual) else {
+                continue;
+            };
+            transform.translation = a.translation.lerp(b.translation, shipment.progress);
+            *visibility = match node_a.visible && node_b.visible {
+                true => Visibility::Visible,
+                false => Visibility::Hidden,
+            };
+        }
+    }
+}
+
+/// Turning logistics off delivers everything still on the links right away
+fn flush_transit(
+    mut commands: Commands,
+    logistics: Res<Logistics>,
+    mut query: Query<(&LinkFlow, &mut Transit)>,
+    mut writer: EventWriter<Delivery>,
+) {
+    if !logistics.is_changed() || logistics.enabled {
+        return;
+    }
+
+    for (flow, mut transit) in query.iter_mut() {
+        let amount = transit.backlog + transit.in_transit();
+        for shipment in transit.shipments.drain(..) {
+            commands.entity(shipment.visual).despawn();
+        }
+        transit.backlog = 0.0;
+
+        if amount > 0.0 {
+            writer.send(Delivery {
+                ingredient: flow.ingredient,
+                amount,
+            });
+        }
+    }
+}
+
+fn deliver_shipments(mut reader: EventReader<Delivery>, mut ingredients: ResMut<Ingredients>) {
+    for delivery in reader.iter() {
+        let ingredient = ingredients.get_mut(delivery.ingredient);
+        let lost = ingredient.add_ingredient(delivery.amount);
+        if lost > 0.0 {
+            info!(
+                "Delivered more {} than fits, {} was lost",
+                ingredient.name, lost
+            );
+        }
+        ingredient.total_produced += delivery.amount;
+    }
+}
+
+fn update_stock_totals(
+    transit_query: Query<(&LinkFlow, &Transit)>,
+    ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    mut totals: ResMut<StockTotals>,
+) {
+    totals.ingredients.clear();
+    for (ty, ingredient) in ingredients.iter() {
+        totals.ingredients.push(StockBreakdown {
+            stored: ingredient.current,
+            buffered: recipes.buffered(ty),
+            in_transit: 0.0,
+        });
+    }
+    for (flow, transit) in transit_query.iter() {
+        if let Some(breakdown) = totals.ingredients.get_mut(flow.ingredient.ix()) {
+            breakdown.in_transit += transit.backlog + transit.in_transit();
+        }
+    }
+}
+
+pub struct TransportPlugin;
+
+impl Plugin for TransportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Delivery>()
+            .init_resource::<Logistics>()
+            .init_resource::<StockTotals>()
+            .add_systems(Startup, setup_transit_visuals)
+            .add_systems(
+                Update,
+                (
+                    add_transit,
+                    sync_buffers,
+                    fill_buffers,
+                    // Both decide from the same flag whether outputs go to the stock or the links,
+                    // and only cycles that weren't cancelled are completed
+                    load_outputs.after(process_recipe_events),
+                    dispatch_shipments,
+                    move_shipments,
+                    flush_transit,
+                    deliver_shipments,
+                    update_stock_totals,
+                )
+                    .chain(),
+            );
+    }
+}
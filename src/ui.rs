@@ -6,16 +6,42 @@ use bevy_egui::{
 };
 
 use crate::{
-    camera::SetTarget,
-    ingredient::{IngredientIndex, Ingredients},
-    node::NodeRegistry,
-    recipe::{Recipe, Recipes},
+    bookmark::BookmarkEvent,
+    camera::{CameraFocus, FitAll, MainCamera, MapMode, SetTarget},
+    ingredient::Ingredients,
+    link::Link,
+    node::{Node, NodeRegistry, NodeType},
+    recipe::{
+        Allocation, AllocationStrategy, CancelRefund, Recipe, RecipeEvent, RecipeHolder,
+        RecipeIndex, Recipes,
+    },
+    research::{Research, ResearchEvent, ResearchIndex, Researches},
+    save::SaveData,
+    selection::Selection,
+    site::Sites,
+    transport::{Logistics, StockBreakdown, StockTotals},
+    upgrade::{UpgradeEvent, Upgrades},
     utils,
 };
 
+/// Which of the windows opened from the toolbar are currently shown
 #[derive(Debug, Default, Resource)]
-pub struct SelectedNode {
-    pub selected: Option<IngredientIndex>,
+pub struct OpenWindows {
+    pub research: bool,
+    pub bookmarks: bool,
+    pub shipping: bool,
+}
+
+/// Whether egui is using the keyboard, e.g. for a text field, so keys shouldn't also
+/// control the game
+#[derive(Debug, Default, Resource)]
+pub struct UiFocus {
+    pub wants_keyboard: bool,
+}
+
+/// Run condition for systems reading the keyboard
+pub fn not_typing(focus: Res<UiFocus>) -> bool {
+    !focus.wants_keyboard
 }
 
 pub struct UiPlugin;
@@ -23,12 +49,26 @@ pub struct UiPlugin;
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(EguiPlugin)
-            .init_resource::<SelectedNode>()
+            .init_resource::<OpenWindows>()
+            .init_resource::<UiFocus>()
+            .add_systems(PreUpdate, track_ui_focus.after(EguiSet::BeginFrame))
             .add_systems(
                 Startup,
                 configure_visuals.after(EguiStartupSet::InitContexts),
             )
-            .add_systems(PostUpdate, draw_ui.after(EguiSet::InitContexts));
+            .add_systems(
+                PostUpdate,
+                (
+                    draw_ui,
+                    draw_upgrades,
+                    draw_toolbar,
+                    draw_research,
+                    draw_bookmarks,
+                    draw_minimap,
+                    draw_watch_list,
+                )
+                    .after(EguiSet::InitContexts),
+            );
     }
 }
 
@@ -39,16 +79,36 @@ fn configure_visuals(mut contexts: EguiContexts) {
     });
 }
 
+fn track_ui_focus(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
+    mut focus: ResMut<UiFocus>,
+) {
+    let wants_keyboard = main_window_query
+        .get_single()
+        .ok()
+        .and_then(|window| contexts.try_ctx_for_window_mut(window))
+        .is_some_and(|ctx| ctx.wants_keyboard_input());
+    if focus.wants_keyboard != wants_keyboard {
+        focus.wants_keyboard = wants_keyboard;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn draw_ui(
     mut contexts: EguiContexts,
-    ingredients: Res<Ingredients>,
+    mut ingredients: ResMut<Ingredients>,
     mut hide_display: Local<bool>,
     mut owned_labels: Local<Vec<String>>,
     main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
     mut writer: EventWriter<SetTarget>,
     node_registry: Res<NodeRegistry>,
-    selected_node: Res<SelectedNode>,
-    recipes: Res<Recipes>,
+    selection: Res<Selection>,
+    mut recipes: ResMut<Recipes>,
+    totals: Res<StockTotals>,
+    sites: Res<Sites>,
+    mut recipe_writer: EventWriter<RecipeEvent>,
+    refund: Res<CancelRefund>,
 ) {
     let Ok(main_window) = main_window_query.get_single() else {
         return;
@@ -64,20 +124,28 @@ fn draw_ui(
     egui::SidePanel::left("ingredient display")
         .resizable(false)
         .show_animated(ctx, !*hide_display, |ui| {
+            // Only the stock of the site the player is looking at
             for (ty, ingr) in ingredients.iter() {
+                if !ingr.unlocked || ingr.site != sites.active() {
+                    continue;
+                }
                 ui.horizontal(|ui| {
                     use std::fmt::Write;
                     let mut label = &mut owned_labels[ty.ix()];
                     label.clear();
                     write!(label, "{}: ", &ingr.name).unwrap();
-                    utils::write_format_number(&mut label, ingr.current).unwrap();
-                    if ui
+                    // Everything owned, not just what's in the ingredient's own stock
+                    let stock = totals.get(ty);
+                    utils::write_format_number(&mut label, stock.total()).unwrap();
+                    let mut response = ui
                         .button(&*label)
-                        .on_hover_cursor(egui::CursorIcon::PointingHand)
-                        .clicked()
-                    {
+                        .on_hover_cursor(egui::CursorIcon::PointingHand);
+                    if stock.total() > stock.stored {
+                        response = response.on_hover_text(stock_breakdown(stock));
+                    }
+                    if response.clicked() {
                         info!("{} clicked!", &ingr.name);
-                        if let Some(e) = node_registry.get(&ty) {
+                        if let Some(e) = node_registry.get(&NodeType::Ingredient(ty)) {
                             writer.send(SetTarget(*e));
                         }
                     }
@@ -104,33 +172,668 @@ fn draw_ui(
             };
         });
 
-    if let Some(selected_ingredient) = selected_node.selected {
+    if let Some(selected) = selection.primary() {
         egui::SidePanel::right("node panel")
             .resizable(false)
             .show(ctx, |ui| {
                 egui::ScrollArea::vertical().show(ui, |ui| {
+                    stock_settings(ui, selected, &mut ingredients, &mut recipes);
                     egui::Grid::new("recipe list")
                         .num_columns(1)
                         .striped(true)
-                        .show(ui, |ui| {
-                            // TODO: cache these results somewhere
-                            for (_i, recipe_holder) in recipes.enumerate() {
-                                if recipe_holder
-                                    .recipe
-                                    .output
-                                    .iter()
-                                    .any(|(i, _)| *i == selected_ingredient)
-                                {
-                                    recipe_item(ui, &recipe_holder.recipe, &ingredients);
-                                    ui.end_row();
+                        .show(ui, |ui| match selected {
+                            NodeType::Ingredient(selected_ingredient) => {
+                                // TODO: cache these results somewhere
+                                for (_i, recipe_holder) in recipes.enumerate() {
+                                    if recipe_holder.unlocked
+                                        && recipe_holder
+                                            .recipe
+                                            .output
+                                            .iter()
+                                            .any(|(i, _)| *i == selected_ingredient)
+                                    {
+                                        recipe_item(ui, &recipe_holder.recipe, &ingredients);
+                                        ui.end_row();
+                                    }
                                 }
                             }
+                            NodeType::Recipe(selected_recipe) => {
+                                let holder = recipes.get_recipe_holder(&selected_recipe);
+                                ui.heading(&holder.recipe.id);
+                                ui.end_row();
+                                recipe_item(ui, &holder.recipe, &ingredients);
+                                ui.end_row();
+                                recipe_controls(
+                                    ui,
+                                    selected_recipe,
+                                    holder,
+                                    &refund,
+                                    &mut recipe_writer,
+                                );
+                            }
                         });
                 });
             });
     }
 }
 
+/// A checkbox turning an amount on or off, with a field to edit it. Returns the edited value.
+fn optional_amount(ui: &mut Ui, label: &str, current: Option<f64>, default: f64) -> Option<f64> {
+    let mut enabled = current.is_some();
+    let mut amount = current.unwrap_or(default);
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut enabled, label);
+        ui.add_enabled(
+            enabled,
+            egui::DragValue::new(&mut amount)
+                .clamp_range(0.0..=f64::MAX)
+                .speed(1.0),
+        );
+    });
+    enabled.then_some(amount)
+}
+
+/// Keep thresholds of an ingredient, or the input floors and output targets of a recipe.
+/// Only writes when edited, so the resources aren't marked as changed every frame.
+fn stock_settings(
+    ui: &mut Ui,
+    selected: NodeType,
+    ingredients: &mut ResMut<Ingredients>,
+    recipes: &mut ResMut<Recipes>,
+) {
+    match selected {
+        NodeType::Ingredient(i) => {
+            let ingredient = ingredients.get(i);
+            let current = ingredient.keep;
+            let keep = optional_amount(ui, "Keep at least", current, ingredient.current);
+            if keep != current {
+                ingredients.get_mut(i).keep = keep;
+            }
+            ui.weak("Recipes never take the stock below this");
+        }
+        NodeType::Recipe(i) => {
+            let holder = recipes.get_recipe_holder(&i);
+
+            let mut floors = vec![];
+            for (k, (ty, _)) in holder.recipe.input.iter().enumerate() {
+                let ingredient = ingredients.get(*ty);
+                let current = holder.input_floors[k];
+                let label = format!("Leave {} at", ingredient.name);
+                let floor = optional_amount(ui, &label, current, ingredient.keep.unwrap_or(0.0));
+                if floor != current {
+                    floors.push((k, floor));
+                }
+            }
+            if !holder.recipe.input.is_empty() {
+                ui.weak("The higher of these and the ingredient's own keep amount applies");
+            }
+
+            let mut targets = vec![];
+            for (k, (ty, _)) in holder.recipe.output.iter().enumerate() {
+                let ingredient = ingredients.get(*ty);
+                let current = holder.output_targets[k];
+                let label = format!("Stop at {}", ingredient.name);
+                let target = optional_amount(ui, &label, current, ingredient.current);
+                if target != current {
+                    targets.push((k, target));
+                }
+            }
+            ui.weak("Automation idles once an output reaches its target or fills up");
+
+            for (k, floor) in floors {
+                recipes.get_recipe_holder_mut(&i).input_floors[k] = floor;
+            }
+            for (k, target) in targets {
+                recipes.get_recipe_holder_mut(&i).output_targets[k] = target;
+            }
+        }
+    }
+    ui.separator();
+}
+
+/// Where the amount of an ingredient is kept, one place per line
+pub(crate) fn stock_breakdown(stock: StockBreakdown) -> String {
+    format!(
+        "In stock: {}\nIn recipe buffers: {}\nIn transit: {}",
+        utils::format_number(stock.stored),
+        utils::format_number(stock.buffered),
+        utils::format_number(stock.in_transit)
+    )
+}
+
+fn draw_upgrades(
+    mut contexts: EguiContexts,
+    ingredients: Res<Ingredients>,
+    upgrades: Res<Upgrades>,
+    main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
+    mut writer: EventWriter<UpgradeEvent>,
+) {
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    let mut available = upgrades
+        .enumerate()
+        .filter(|(_, upgrade)| upgrade.unlocked && !upgrade.purchased)
+        .peekable();
+    if available.peek().is_none() {
+        return;
+    }
+
+    egui::Window::new("Upgrades")
+        .anchor(egui::Align2::LEFT_BOTTOM, [200.0, -8.0])
+        .resizable(false)
+        .show(ctx, |ui| {
+            for (i, upgrade) in available {
+                use std::fmt::Write;
+                let mut label = format!("{} (", upgrade.name);
+                for (n, (ty, q)) in upgrade.cost.iter().enumerate() {
+                    if n > 0 {
+                        write!(&mut label, ", ").unwrap();
+                    }
+                    utils::write_format_number(&mut label, q.value()).unwrap();
+                    write!(&mut label, " {}", ingredients.get(*ty).name).unwrap();
+                }
+                write!(&mut label, ")").unwrap();
+
+                if ui
+                    .add_enabled(upgrade.can_afford(&ingredients), egui::Button::new(label))
+                    .on_hover_cursor(egui::CursorIcon::PointingHand)
+                    .clicked()
+                {
+                    writer.send(UpgradeEvent::Purchase(i));
+                }
+            }
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_toolbar(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
+    mut open_windows: ResMut<OpenWindows>,
+    mut fit_all_writer: EventWriter<FitAll>,
+    mut map_mode: ResMut<MapMode>,
+    mut save_data: ResMut<SaveData>,
+    mut logistics: ResMut<Logistics>,
+    mut allocation: ResMut<Allocation>,
+) {
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    egui::Area::new("toolbar")
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -8.0])
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.toggle_value(&mut open_windows.research, "Research");
+                ui.toggle_value(&mut open_windows.bookmarks, "Bookmarks");
+                if ui.button("Fit all").clicked() {
+                    fit_all_writer.send(FitAll);
+                }
+                // Only touch the resource when toggled, so it isn't marked as changed every frame
+                let mut map = map_mode.enabled;
+                if ui.toggle_value(&mut map, "Map").changed() {
+                    map_mode.enabled = map;
+                }
+                let mut enabled = logistics.enabled;
+                if ui
+                    .toggle_value(&mut enabled, "Logistics")
+                    .on_hover_text("Items travel along the links as fast as their belts allow, and recipes work from their own input buffers")
+                    .changed()
+                {
+                    logistics.enabled = enabled;
+                }
+                let mut strategy = allocation.strategy;
+                egui::ComboBox::from_id_source("allocation strategy")
+                    .selected_text(strategy.name())
+                    .show_ui(ui, |ui| {
+                        for option in AllocationStrategy::ALL {
+                            ui.selectable_value(&mut strategy, option, option.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("How recipes share inputs there isn't enough of for all of them");
+                if strategy != allocation.strategy {
+                    allocation.strategy = strategy;
+                }
+                let hidden = save_data.hidden_nodes.len();
+                if hidden > 0 && ui.button(format!("Unhide {} nodes", hidden)).clicked() {
+                    save_data.hidden_nodes.clear();
+                }
+            });
+        });
+}
+
+/// Shows the research projects as a tree, with a column for every level of prerequisites
+fn draw_research(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
+    mut open_windows: ResMut<OpenWindows>,
+    researches: Res<Researches>,
+    ingredients: Res<Ingredients>,
+    mut writer: EventWriter<ResearchEvent>,
+) {
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    let depths = researches.depths();
+    let max_depth = depths.iter().copied().max().unwrap_or(0);
+
+    egui::Window::new("Research")
+        .open(&mut open_windows.research)
+        .show(ctx, |ui| {
+            egui::ScrollArea::both().show(ui, |ui| {
+                let mut rects = vec![egui::Rect::NOTHING; researches.len()];
+
+                ui.horizontal_top(|ui| {
+                    for depth in 0..=max_depth {
+                        ui.vertical(|ui| {
+                            for (i, research) in researches.enumerate() {
+                                if depths[i.ix()] != depth {
+                                    continue;
+                                }
+                                rects[i.ix()] = research_item(
+                                    ui,
+                                    i,
+                                    research,
+                                    &researches,
+                                    &ingredients,
+                                    &mut writer,
+                                )
+                                .rect;
+                            }
+                        });
+                        ui.add_space(24.0);
+                    }
+                });
+
+                let stroke = egui::Stroke::new(1.0, egui::Color32::GRAY);
+                for (i, research) in researches.enumerate() {
+                    for p in research.prerequisites.iter() {
+                        ui.painter().line_segment(
+                            [rects[p.ix()].right_center(), rects[i.ix()].left_center()],
+                            stroke,
+                        );
+                    }
+                }
+            });
+        });
+}
+
+fn draw_bookmarks(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
+    mut open_windows: ResMut<OpenWindows>,
+    save_data: Res<SaveData>,
+    mut writer: EventWriter<BookmarkEvent>,
+) {
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    egui::Window::new("Bookmarks")
+        .open(&mut open_windows.bookmarks)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.weak("Ctrl+1..9 to store, 1..9 to jump");
+            egui::Grid::new("bookmark list")
+                .num_columns(4)
+                .striped(true)
+                .show(ui, |ui| {
+                    for slot in 1..=9 {
+                        let bookmark = save_data.bookmarks.get(&slot);
+                        let label = match bookmark {
+                            None => format!("{}: empty", slot),
+                            Some(b) => match &b.node {
+                                Some(id) => format!("{}: {}", slot, id),
+                                None => {
+                                    format!("{}: ({:.0}, {:.0})", slot, b.target[0], b.target[2])
+                                }
+                            },
+                        };
+                        ui.label(label);
+                        if ui
+                            .add_enabled(bookmark.is_some(), egui::Button::new("Go"))
+                            .clicked()
+                        {
+                            writer.send(BookmarkEvent::Jump(slot));
+                        }
+                        if ui.button("Set").clicked() {
+                            writer.send(BookmarkEvent::Store(slot));
+                        }
+                        if ui
+                            .add_enabled(bookmark.is_some(), egui::Button::new("Clear"))
+                            .clicked()
+                        {
+                            writer.send(BookmarkEvent::Clear(slot));
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+}
+
+/// Shows every visible node and link from above, along with the part of the ground the camera
+/// sees. Clicking or dragging on it moves the camera there.
+fn draw_minimap(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
+    mut camera_query: Query<(&Camera, &GlobalTransform, &mut CameraFocus), With<MainCamera>>,
+    node_query: Query<(&Transform, &Node)>,
+    link_query: Query<&Link>,
+    ingredients: Res<Ingredients>,
+) {
+    // Side length of the map, in points
+    const SIZE: f32 = 180.0;
+    // Empty space around the outermost nodes, in world units
+    const MARGIN: f32 = 2.0;
+    // How far along the ground the view is drawn when the camera looks above the horizon
+    const HORIZON_DISTANCE: f32 = 100.0;
+
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+    let Ok((camera, camera_transform, mut focus)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    let ground = |translation: Vec3| Vec2::new(translation.x, translation.z);
+    let Some((min, max)) = node_query
+        .iter()
+        .filter(|(_, node)| node.visible)
+        .map(|(transform, _)| ground(transform.translation))
+        .fold(None, |bounds, p| match bounds {
+            None => Some((p, p)),
+            Some((min, max)) => Some((Vec2::min(min, p), Vec2::max(max, p))),
+        })
+    else {
+        return;
+    };
+    let center = (min + max) / 2.0;
+    let extent = f32::max((max - min).max_element(), 1.0) + 2.0 * MARGIN;
+    let scale = SIZE / extent;
+
+    egui::Area::new("minimap")
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-8.0, -8.0])
+        .show(ctx, |ui| {
+            let (response, painter) =
+                ui.allocate_painter(egui::vec2(SIZE, SIZE), egui::Sense::click_and_drag());
+            let rect = response.rect;
+
+            // Seen from the default camera position: +z is up and +x is to the left
+            let to_screen = |p: Vec2| {
+                let p = (p - center) * scale;
+                rect.center() + egui::vec2(-p.x, -p.y)
+            };
+            let to_world = |p: egui::Pos2| {
+                let p = p - rect.center();
+                center + Vec2::new(-p.x, -p.y) / scale
+            };
+
+            painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(192));
+            painter.rect_stroke(rect, 2.0, egui::Stroke::new(1.0, egui::Color32::DARK_GRAY));
+
+            let link_stroke = egui::Stroke::new(1.0, egui::Color32::from_gray(96));
+            for link in link_query.iter() {
+                let (Ok((from, from_node)), Ok((to, to_node))) =
+                    (node_query.get(link.from), node_query.get(link.to))
+                else {
+                    continue;
+                };
+                if from_node.visible && to_node.visible {
+                    painter.line_segment(
+                        [
+                            to_screen(ground(from.translation)),
+                            to_screen(ground(to.translation)),
+                        ],
+                        link_stroke,
+                    );
+                }
+            }
+
+            for (transform, node) in node_query.iter() {
+                if !node.visible {
+                    continue;
+                }
+                let (color, radius) = match node.ty {
+                    NodeType::Ingredient(i) => {
+                        let [r, g, b, a] = ingredients.get(i).color.as_rgba_u8();
+                        (egui::Color32::from_rgba_unmultiplied(r, g, b, a), 4.0)
+                    }
+                    NodeType::Recipe(_) => (egui::Color32::WHITE, 2.0),
+                };
+                painter.circle_filled(to_screen(ground(transform.translation)), radius, color);
+            }
+
+            // Where the corners of the screen hit the ground
+            if let Some(size) = camera.logical_viewport_size() {
+                let corners = [
+                    Vec2::ZERO,
+                    Vec2::new(size.x, 0.0),
+                    size,
+                    Vec2::new(0.0, size.y),
+                ];
+                let points = corners
+                    .iter()
+                    .filter_map(|corner| camera.viewport_to_world(camera_transform, *corner))
+                    .map(|ray| match ray.intersect_plane(Vec3::ZERO, Vec3::Y) {
+                        Some(t) => ground(ray.get_point(t)),
+                        None => {
+                            ground(ray.origin)
+                                + ground(ray.direction).normalize_or_zero() * HORIZON_DISTANCE
+                        }
+                    })
+                    .map(to_screen)
+                    .collect();
+                painter.with_clip_rect(rect).add(egui::Shape::closed_line(
+                    points,
+                    egui::Stroke::new(1.0, egui::Color32::WHITE),
+                ));
+            }
+
+            if response.clicked() || response.dragged() {
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    let target = to_world(pointer);
+                    focus.set_target(Vec3::new(target.x, 0.0, target.y));
+                }
+            }
+        });
+}
+
+/// Lists the ingredients and recipes pinned from the node context menu
+fn draw_watch_list(
+    mut contexts: EguiContexts,
+    main_window_query: Query<Entity, With<bevy::window::PrimaryWindow>>,
+    mut save_data: ResMut<SaveData>,
+    ingredients: Res<Ingredients>,
+    recipes: Res<Recipes>,
+    node_registry: Res<NodeRegistry>,
+    mut writer: EventWriter<SetTarget>,
+) {
+    if save_data.watch_list.is_empty() {
+        return;
+    }
+    let Ok(main_window) = main_window_query.get_single() else {
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_for_window_mut(main_window) else {
+        return;
+    };
+
+    let mut unpinned = None;
+    egui::Window::new("Watch list")
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("watch list")
+                .num_columns(4)
+                .striped(true)
+                .show(ui, |ui| {
+                    for id in save_data.watch_list.iter() {
+                        let Some(ty) = NodeType::from_id(id, &ingredients, &recipes) else {
+                            continue;
+                        };
+
+                        let (name, amount, rate) = match ty {
+                            NodeType::Ingredient(i) => {
+                                let ingredient = ingredients.get(i);
+                                (
+                                    ingredient.name.as_str(),
+                                    utils::format_number(ingredient.current),
+                                    utils::format_rate(recipes.net_rate(i)),
+                                )
+                            }
+                            NodeType::Recipe(i) => {
+                                let holder = recipes.get_recipe_holder(&i);
+                                let state = match holder.stalled(&ingredients) {
+                                    true => "Stalled",
+                                    false => "Running",
+                                };
+                                (
+                                    holder.recipe.id.as_str(),
+                                    state.to_string(),
+                                    format!("{:.2} cycles/s", holder.rate),
+                                )
+                            }
+                        };
+
+                        if ui
+                            .button(name)
+                            .on_hover_cursor(egui::CursorIcon::PointingHand)
+                            .clicked()
+                        {
+                            if let Some(e) = node_registry.get(&ty) {
+                                writer.send(SetTarget(*e));
+                            }
+                        }
+                        ui.label(amount);
+                        ui.label(rate);
+                        if ui.small_button("x").on_hover_text("Unpin").clicked() {
+                            unpinned = Some(id.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+
+    if let Some(id) = unpinned {
+        save_data.watch_list.retain(|pinned| *pinned != id);
+    }
+}
+
+fn research_item(
+    ui: &mut Ui,
+    i: ResearchIndex,
+    research: &Research,
+    researches: &Researches,
+    ingredients: &Ingredients,
+    writer: &mut EventWriter<ResearchEvent>,
+) -> egui::Response {
+    use std::fmt::Write;
+    let mut cost = String::new();
+    for (ty, q) in research.cost.iter() {
+        utils::write_format_number(&mut cost, q.value()).unwrap();
+        write!(&mut cost, " {}/s, ", ingredients.get(*ty).name).unwrap();
+    }
+    write!(&mut cost, "for {}s", research.duration.value()).unwrap();
+
+    egui::Frame::none()
+        .outer_margin(egui::Margin::same(2.0))
+        .inner_margin(egui::Margin::same(4.0))
+        .stroke(egui::Stroke::new(1.0, egui::Color32::DARK_GRAY))
+        .rounding(egui::Rounding::same(2.0))
+        .show(ui, |ui| {
+            ui.set_width(180.0);
+            ui.strong(&research.name);
+            ui.label(cost);
+
+            if research.completed {
+                ui.label("Done");
+            } else if researches.active == Some(i) {
+                ui.add(egui::ProgressBar::new(research.fraction_done() as f32).show_percentage());
+                if ui.button("Pause").clicked() {
+                    writer.send(ResearchEvent::Stop);
+                }
+            } else if research.prerequisites_met(researches) {
+                if research.progress > 0.0 {
+                    ui.add(egui::ProgressBar::new(research.fraction_done() as f32));
+                }
+                if ui.button("Start").clicked() {
+                    writer.send(ResearchEvent::Start(i));
+                }
+            } else {
+                ui.weak("Requires earlier research");
+            }
+        })
+        .response
+}
+
+/// Progress of the recipe, with buttons to queue crafts and cancel the running cycle
+fn recipe_controls(
+    ui: &mut Ui,
+    i: RecipeIndex,
+    holder: &RecipeHolder,
+    refund: &CancelRefund,
+    writer: &mut EventWriter<RecipeEvent>,
+) {
+    if holder.started {
+        let progress = holder.time / holder.cycle_time();
+        ui.add(egui::ProgressBar::new(progress as f32));
+        ui.end_row();
+    }
+
+    // Automatic recipes can be queued from the palette too, but only manual ones need it here
+    if !holder.recipe.automatic || holder.queued > 0 {
+        ui.horizontal(|ui| {
+            let id = egui::Id::new("craft count").with(&holder.recipe.id);
+            let mut count = ui.data_mut(|data| *data.get_temp_mut_or(id, 1u32));
+            ui.add(egui::DragValue::new(&mut count).clamp_range(1..=999));
+            ui.data_mut(|data| data.insert_temp(id, count));
+            if ui.button("Craft").clicked() {
+                writer.send(RecipeEvent::QueueRecipe(i, count));
+            }
+        });
+        ui.end_row();
+
+        if holder.queued > 0 {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} queued, done in {}s",
+                    holder.queued,
+                    utils::format_number(holder.queue_eta())
+                ));
+                if ui.button("Clear").clicked() {
+                    writer.send(RecipeEvent::ClearQueue(i));
+                }
+            });
+            ui.end_row();
+        }
+    }
+
+    if holder.started {
+        if ui.button(refund.button_label()).clicked() {
+            writer.send(RecipeEvent::CancelRecipe(i));
+        }
+        ui.end_row();
+    }
+}
+
 fn recipe_item(ui: &mut Ui, recipe: &Recipe, ingredients: &Ingredients) {
     use std::fmt::Write;
     let mut s1 = "I recieve: ".to_string();
@@ -0,0 +1,106 @@
+use bevy::prelude::*;
+
+use crate::{
+    ingredient::{IngredientIndex, Ingredients},
+    recipe::{process_recipe_events, RecipeIndex, Recipes},
+    upgrade::{UpgradeIndex, Upgrades},
+};
+
+/// Something that starts out locked and is revealed to the player later
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Unlockable {
+    Ingredient(IngredientIndex),
+    Recipe(RecipeIndex),
+    Upgrade(UpgradeIndex),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnlockCondition {
+    /// Met the first time any amount of the ingredient is produced
+    Produced(IngredientIndex),
+    /// Met once the stock of the ingredient reaches the given amount
+    StockAtLeast(IngredientIndex, f64),
+    /// Met once the upgrade has been bought
+    Upgrade(UpgradeIndex),
+}
+
+impl UnlockCondition {
+    pub fn is_met(&self, ingredients: &Ingredients, upgrades: &Upgrades) -> bool {
+        match *self {
+            UnlockCondition::Produced(i) => ingredients.get(i).total_produced > 0.0,
+            UnlockCondition::StockAtLeast(i, amount) => ingredients.get(i).current >= amount,
+            UnlockCondition::Upgrade(i) => upgrades.get(i).purchased,
+        }
+    }
+}
+
+/// Conditions still waiting to be met. A target with several conditions is unlocked
+/// as soon as any one of them is met.
+#[derive(Resource, Default)]
+pub struct Unlocks {
+    pending: Vec<(Unlockable, UnlockCondition)>,
+}
+
+impl Unlocks {
+    pub fn add_unlock(&mut self, target: Unlockable, condition: UnlockCondition) {
+        self.pending.push((target, condition));
+    }
+}
+
+/// Sets whether `target` is unlocked, returning whether it was unlocked before
+pub fn set_unlocked(
+    target: Unlockable,
+    unlocked: bool,
+    ingredients: &mut Ingredients,
+    recipes: &mut Recipes,
+    upgrades: &mut Upgrades,
+) -> bool {
+    let flag = match target {
+        Unlockable::Ingredient(i) => &mut ingredients.get_mut(i).unlocked,
+        Unlockable::Recipe(i) => &mut recipes.get_recipe_holder_mut(&i).unlocked,
+        Unlockable::Upgrade(i) => &mut upgrades.get_mut(i).unlocked,
+    };
+
+    std::mem::replace(flag, unlocked)
+}
+
+/// Sent when something is unlocked, so it can be revealed
+#[derive(Event, Debug)]
+pub struct Unlocked(pub Unlockable);
+
+fn check_unlocks(
+    mut unlocks: ResMut<Unlocks>,
+    mut ingredients: ResMut<Ingredients>,
+    mut recipes: ResMut<Recipes>,
+    mut upgrades: ResMut<Upgrades>,
+    mut writer: EventWriter<Unlocked>,
+) {
+    let mut met = vec![];
+    unlocks.pending.retain(|(target, condition)| {
+        if condition.is_met(&ingredients, &upgrades) {
+            met.push(*target);
+            false
+        } else {
+            true
+        }
+    });
+
+    for target in met {
+        let already_unlocked =
+            set_unlocked(target, true, &mut ingredients, &mut recipes, &mut upgrades);
+
+        if !already_unlocked {
+            writer.send(Unlocked(target));
+        }
+    }
+}
+
+pub struct UnlockPlugin;
+
+impl Plugin for UnlockPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Unlocked>()
+            .init_resource::<Unlocks>()
+            .add_systems(Update, check_unlocks.after(process_recipe_events));
+    }
+}
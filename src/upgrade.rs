@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+
+use crate::{
+    ingredient::{IngredientIndex, Ingredients},
+    quantity::Quantity,
+};
+
+#[derive(Debug)]
+pub struct Upgrade {
+    pub id: String,
+    pub name: String,
+    pub cost: Vec<(IngredientIndex, Quantity)>,
+    pub purchased: bool,
+    pub unlocked: bool,
+}
+
+impl Upgrade {
+    pub fn can_afford(&self, ingredients: &Ingredients) -> bool {
+        self.cost
+            .iter()
+            .all(|&(ty, amount)| ingredients.get(ty).current >= amount.value())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct UpgradeIndex(usize);
+
+#[derive(Resource, Default)]
+pub struct Upgrades {
+    upgrades: Vec<Upgrade>,
+}
+
+impl Upgrades {
+    pub fn add_upgrade(&mut self, upgrade: Upgrade) -> UpgradeIndex {
+        let i = self.upgrades.len();
+        self.upgrades.push(upgrade);
+        UpgradeIndex(i)
+    }
+
+    pub fn get(&self, index: UpgradeIndex) -> &Upgrade {
+        &self.upgrades[index.0]
+    }
+
+    pub fn get_mut(&mut self, index: UpgradeIndex) -> &mut Upgrade {
+        &mut self.upgrades[index.0]
+    }
+
+    pub fn enumerate(&self) -> impl Iterator<Item = (UpgradeIndex, &Upgrade)> {
+        self.upgrades
+            .iter()
+            .enumerate()
+            .map(|(i, u)| (UpgradeIndex(i), u))
+    }
+}
+
+#[derive(Event, Debug)]
+pub enum UpgradeEvent {
+    Purchase(UpgradeIndex),
+}
+
+fn process_upgrade_events(
+    mut upgrades: ResMut<Upgrades>,
+    mut ingredients: ResMut<Ingredients>,
+    mut reader: EventReader<UpgradeEvent>,
+) {
+    for event in reader.into_iter() {
+        match event {
+            UpgradeEvent::Purchase(i) => {
+                let upgrade = upgrades.get_mut(*i);
+
+                if upgrade.purchased {
+                    warn!("Tried to buy upgrade {} more than once", upgrade.id);
+                    continue;
+                }
+
+                if !upgrade.unlocked || !upgrade.can_afford(&ingredients) {
+                    warn!(
+                        "Tried to buy upgrade {} without meeting its cost",
+                        upgrade.id
+                    );
+                    continue;
+                }
+
+                for (ty, amount) in &upgrade.cost {
+                    ingredients.get_mut(*ty).spend_ingredient(amount.value());
+                }
+
+                upgrade.purchased = true;
+                info!("Bought upgrade {}", upgrade.id);
+            }
+        }
+    }
+}
+
+pub struct UpgradePlugin;
+
+impl Plugin for UpgradePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<UpgradeEvent>()
+            .init_resource::<Upgrades>()
+            .add_systems(Update, process_upgrade_events);
+    }
+}
@@ -37,3 +37,23 @@ pub fn write_format_quantity(
     let x = x.value();
     write_format_number(w, x)
 }
+
+/// Formats an amount per second, keeping a couple of decimals for slow rates
+pub fn format_rate(x: f64) -> String {
+    let mut buf = String::new();
+    write_format_rate(&mut buf, x).unwrap();
+    buf
+}
+
+pub fn write_format_rate(w: &mut impl Write, x: f64) -> Result<(), std::fmt::Error> {
+    if x < 0.0 {
+        write!(w, "-")?;
+    }
+    let x = x.abs();
+    if x < 10.0 {
+        write!(w, "{:0.2}", x)?;
+    } else {
+        write_format_number(w, x)?;
+    }
+    write!(w, "/s")
+}